@@ -0,0 +1,169 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+use log::*;
+use tiny_http::{Header, Method, Request, Response, Server};
+
+use crate::control::{ControlError, ControlHandle, SinkStats};
+use crate::key_values::JsonEscaped;
+
+/// A tiny HTTP listener driving `control::handle()`, for services without a shell:
+/// * `GET /spec`: the current log spec, eg: `info,h2=warn`
+/// * `PUT /spec`: replace the log spec by the request body
+/// * `DELETE /spec`: go back to the spec(s) the logger was started with
+/// * `GET /sinks`: the sinks with their stats, as json
+/// * `POST /flush`: flush all sinks
+///
+/// eg: `curl -X PUT --data 'debug,h2=info' http://127.0.0.1:9901/spec`
+/// <br>
+/// The listener is stopped by `stop`, or when the process exits.
+pub struct AdminServer {
+    server: Arc<Server>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl AdminServer {
+    /// the address listened to, None if bound to a unix socket
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.server.server_addr().to_ip()
+    }
+
+    pub fn stop(mut self) {
+        self.server.unblock();
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+    }
+}
+
+/// Listen on `addr`, which must be a loopback address (eg: 127.0.0.1:9901, or 127.0.0.1:0 for any free port).
+pub fn start_admin_server(addr: SocketAddr) -> std::io::Result<AdminServer> {
+    if !addr.ip().is_loopback() {
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                                       format!("admin server must listen on a loopback address, got: {}", addr)));
+    }
+    let server = Server::http(addr).map_err(std::io::Error::other)?;
+    start_serving(server, crate::control::handle())
+}
+
+/// Listen on the unix socket at `path`, eg: `curl --unix-socket /run/portal/log.sock http://localhost/spec`
+#[cfg(unix)]
+pub fn start_admin_server_unix(path: impl AsRef<std::path::Path>) -> std::io::Result<AdminServer> {
+    let server = Server::http_unix(path.as_ref()).map_err(std::io::Error::other)?;
+    start_serving(server, crate::control::handle())
+}
+
+fn start_serving(server: Server, handle: &'static ControlHandle) -> std::io::Result<AdminServer> {
+    let server = Arc::new(server);
+    let thread = {
+        let server = server.clone();
+        std::thread::Builder::new()
+            .name("fblog-admin".to_string())
+            .spawn(move || {
+                for request in server.incoming_requests() {
+                    serve(handle, request);
+                }
+            })?
+    };
+    Ok(AdminServer { server, thread: Some(thread) })
+}
+
+fn serve(handle: &ControlHandle, mut request: Request) {
+    let mut body = String::new();
+    if let Err(err) = request.as_reader().read_to_string(&mut body) {
+        debug!("admin server: could not read request body, err: {}", err);
+    }
+    let (status, content_type, response) = route(handle, request.method(), request.url(), body.trim());
+    let header = Header::from_bytes("Content-Type", content_type).expect("content type must be a valid header");
+    if let Err(err) = request.respond(Response::from_string(response).with_status_code(status).with_header(header)) {
+        debug!("admin server: could not send response, err: {}", err);
+    }
+}
+
+/// returns: status code, content type, body
+fn route(handle: &ControlHandle, method: &Method, url: &str, body: &str) -> (u16, &'static str, String) {
+    let result = match (method, url) {
+        (Method::Get, "/spec") => handle.current_spec().ok_or(ControlError::NotStarted),
+        (Method::Put, "/spec") => handle.set_spec(body).map(|()| handle.current_spec().unwrap_or_default()),
+        (Method::Delete, "/spec") => handle.reset_spec().map(|()| handle.current_spec().unwrap_or_default()),
+        (Method::Get, "/sinks") => {
+            return match handle.sinks() {
+                Ok(sinks) => (200, "application/json", sinks_to_json(&sinks)),
+                Err(err) => error_response(err),
+            };
+        }
+        (Method::Post, "/flush") => handle.flush().map(|()| "flushed".to_string()),
+        (_, "/spec") | (_, "/sinks") | (_, "/flush") => return (405, "text/plain", "method not allowed\n".to_string()),
+        _ => return (404, "text/plain", "not found\n".to_string()),
+    };
+    match result {
+        Ok(text) => (200, "text/plain", text + "\n"),
+        Err(err) => error_response(err),
+    }
+}
+
+fn error_response(err: ControlError) -> (u16, &'static str, String) {
+    let status = match err {
+        ControlError::NotStarted => 503,
+        ControlError::InvalidSpec(_) => 400,
+        ControlError::Reopen(_) => 500,
+        ControlError::Timeout => 504,
+    };
+    (status, "text/plain", format!("{}\n", err))
+}
+
+/// eg: [{"name":"console","spec":"info","written":12,"failed":0}]
+fn sinks_to_json(sinks: &[SinkStats]) -> String {
+    let sinks: Vec<String> = sinks
+        .iter()
+        .map(|sink| format!(r#"{{"name":"{}","spec":"{}","written":{},"failed":{}}}"#,
+                            JsonEscaped(&sink.name), JsonEscaped(&sink.spec), sink.written, sink.failed))
+        .collect();
+    format!("[{}]", sinks.join(","))
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+
+    use super::*;
+
+    /// returns: status code, body
+    fn http(addr: SocketAddr, method: &str, path: &str, body: &str) -> (u16, String) {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} {} HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\nContent-Length: {}\r\n\r\n{}",
+               method, path, body.len(), body).unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+        let status = response.split(' ').nth(1).unwrap().parse().unwrap();
+        let body = response.split_once("\r\n\r\n").map(|(_, body)| body.to_string()).unwrap_or_default();
+        (status, body)
+    }
+
+    #[test]
+    fn test_admin_server() {
+        let handle: &'static ControlHandle = Box::leak(Box::new(ControlHandle::new()));
+        let server = start_serving(Server::http("127.0.0.1:0").unwrap(), handle).unwrap();
+        let addr = server.local_addr().unwrap();
+        assert_eq!(http(addr, "GET", "/spec", "").0, 503);
+
+        crate::control::test::register_recording_backend(handle, "info");
+        assert_eq!(http(addr, "GET", "/spec", ""), (200, "info\n".to_string()));
+        assert_eq!(http(addr, "PUT", "/spec", "debug,h2=warn"), (200, "debug,h2=warn\n".to_string()));
+        assert_eq!(http(addr, "PUT", "/spec", "debug,h2=loud").0, 400);
+        assert_eq!(http(addr, "PUT", "/spec", "").0, 400);
+        assert_eq!(http(addr, "PUT", "/spec", " \n").0, 400);
+        assert_eq!(http(addr, "GET", "/spec", ""), (200, "debug,h2=warn\n".to_string()));
+        assert_eq!(http(addr, "DELETE", "/spec", ""), (200, "info\n".to_string()));
+        assert_eq!(http(addr, "GET", "/sinks", ""),
+                   (200, r#"[{"name":"recording","spec":"info","written":1,"failed":1}]"#.to_string()));
+        assert_eq!(http(addr, "POST", "/flush", ""), (200, "flushed\n".to_string()));
+        assert_eq!(http(addr, "GET", "/flush", "").0, 405);
+        assert_eq!(http(addr, "GET", "/nope", "").0, 404);
+        server.stop();
+
+        assert!(start_admin_server("0.0.0.0:0".parse().unwrap()).is_err());
+    }
+}
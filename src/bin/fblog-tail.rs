@@ -0,0 +1,422 @@
+//! Pretty-prints and filters the log files written by fblog, eg:
+//! ```text
+//! fblog-tail -f --level warn --module portal::db /data/log/portal_rCURRENT.log
+//! fblog-tail --since "2021-11-14 11:00" --grep "timeout|refused" /data/log/
+//! kubectl logs portal | fblog-tail --host 10.0.0.7
+//! ```
+//! Reads the lines `fblog::parse` knows: text (with the default template), JSON and syslog,
+//! from stdin if no path is given.
+//! <br>
+//! A `<name>_rCURRENT.log` file is read after its rotated siblings (`<name>_r2021-11-14_11-51-24.log`, ...),
+//! a directory is read as all the log files in it.
+//! With `--follow`, the current files are followed across rotations.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use chrono::{DateTime, FixedOffset, Local, NaiveDate, NaiveDateTime, TimeZone};
+use clap::{Parser, ValueEnum};
+use fblog::color;
+use fblog::parse::{parse_line, ParsedRecord};
+use log::Level;
+use regex::Regex;
+
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+#[derive(Debug, Parser)]
+#[command(name = "fblog-tail", about = "Pretty-print and filter fblog log files")]
+struct Args {
+    /// log files or directories, stdin if none
+    paths: Vec<PathBuf>,
+
+    /// keep reading the current files as they grow, across rotations
+    #[arg(short, long)]
+    follow: bool,
+
+    /// only the records at this level or above, eg: "warn"
+    #[arg(short, long)]
+    level: Option<Level>,
+
+    /// only the records of the modules starting with this, can be repeated
+    #[arg(short, long = "module")]
+    modules: Vec<String>,
+
+    /// only the records at or after this time, eg: "2021-11-14 11:00", "2021-11-14T11:00:00+08:00"
+    #[arg(long, value_parser = parse_time)]
+    since: Option<DateTime<FixedOffset>>,
+
+    /// only the records before this time
+    #[arg(long, value_parser = parse_time)]
+    until: Option<DateTime<FixedOffset>>,
+
+    /// only the records of the hosts whose name contains this, or with this ip
+    #[arg(long)]
+    host: Option<String>,
+
+    /// only the records whose message or key-values match this regex
+    #[arg(short = 'e', long)]
+    grep: Option<Regex>,
+
+    #[arg(long, value_enum, default_value = "auto")]
+    color: ColorChoice,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum ColorChoice {
+    /// if stdout is a terminal and NO_COLOR is not set
+    Auto,
+    Always,
+    Never,
+}
+
+/// a time given on the command line, local time if without offset
+fn parse_time(s: &str) -> Result<DateTime<FixedOffset>, String> {
+    if let Some(time) = fblog::parse::parse_time(s) {
+        return Ok(time);
+    }
+    let naive = ["%Y-%m-%d %H:%M:%S%.f", "%Y-%m-%dT%H:%M:%S%.f", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(s, format).ok())
+        .or_else(|| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok().and_then(|date| date.and_hms_opt(0, 0, 0)));
+    naive
+        .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+        .map(|time| time.fixed_offset())
+        .ok_or_else(|| format!("invalid time: {:?}, eg: \"2021-11-14 11:00\"", s))
+}
+
+struct Filter {
+    level: Option<Level>,
+    modules: Vec<String>,
+    since: Option<DateTime<FixedOffset>>,
+    until: Option<DateTime<FixedOffset>>,
+    host: Option<String>,
+    grep: Option<Regex>,
+}
+
+impl Filter {
+    fn from_args(args: &Args) -> Self {
+        Self {
+            level: args.level,
+            modules: args.modules.clone(),
+            since: args.since,
+            until: args.until,
+            host: args.host.clone(),
+            grep: args.grep.clone(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.level.is_none() && self.modules.is_empty() && self.since.is_none() && self.until.is_none()
+            && self.host.is_none() && self.grep.is_none()
+    }
+
+    /// the records without a time are kept by --since and --until
+    fn matches(&self, record: &ParsedRecord) -> bool {
+        self.level.is_none_or(|level| record.level <= level)
+            && (self.modules.is_empty() || record.module.as_ref().is_some_and(|module| {
+                self.modules.iter().any(|prefix| module.starts_with(prefix.as_str()))
+            }))
+            && self.since.is_none_or(|since| record.time.is_none_or(|time| time >= since))
+            && self.until.is_none_or(|until| record.time.is_none_or(|time| time < until))
+            && self.host.as_ref().is_none_or(|host| {
+                record.host.as_ref().is_some_and(|name| name.contains(host.as_str())) || record.ip.as_ref() == Some(host)
+            })
+            && self.grep.as_ref().is_none_or(|grep| {
+                grep.is_match(&record.message)
+                    || record.key_values.iter().any(|(key, value)| grep.is_match(&format!("{}={}", key, value)))
+            })
+    }
+}
+
+struct Printer<W: Write> {
+    out: W,
+    filter: Filter,
+    colored: bool,
+    /// the lines that are not records (eg: the rest of a multi-line message) follow the record before them
+    last_shown: bool,
+}
+
+impl<W: Write> Printer<W> {
+    fn new(out: W, filter: Filter, colored: bool) -> Self {
+        let last_shown = filter.is_empty();
+        Self { out, filter, colored, last_shown }
+    }
+
+    fn print_line(&mut self, line: &str) -> io::Result<()> {
+        let line = line.trim_end_matches(['\n', '\r']);
+        match parse_line(line) {
+            Some(record) => {
+                self.last_shown = self.filter.matches(&record);
+                if self.last_shown {
+                    self.print_record(&record)?;
+                }
+            }
+            None if self.last_shown => writeln!(self.out, "{}", line)?,
+            None => {}
+        }
+        Ok(())
+    }
+
+    /// `11-14 11:51:24.123 WARN  host module file:line msg k=v`, syslog records without the location:
+    /// `11-14 11:51:24.000 WARN  host process[pid] msg k=v`
+    fn print_record(&mut self, record: &ParsedRecord) -> io::Result<()> {
+        let (level_style, dim, reset) = if self.colored {
+            (color::level_style(record.level), color::DIM, color::RESET)
+        } else {
+            ("", "", "")
+        };
+        let time = record.time.map_or_else(|| "-".to_string(), |time| time.format("%m-%d %H:%M:%S%.3f").to_string());
+        let host = record.host.as_ref().or(record.ip.as_ref()).map_or("-", String::as_str);
+        let source = match (&record.module, &record.process) {
+            (Some(module), _) => format!("{} {}:{}", module, record.file.as_deref().unwrap_or("<unnamed>"), record.line.unwrap_or(0)),
+            (None, Some(process)) => record.pid.map_or_else(|| process.clone(), |pid| format!("{}[{}]", process, pid)),
+            (None, None) => "-".to_string(),
+        };
+        write!(self.out, "{}{}{} {}{:<5}{} {}{} {}{} {}{}{}",
+               dim, time, reset,
+               level_style, record.level, reset,
+               dim, host, source, reset,
+               level_style, record.message, reset)?;
+        for (key, value) in &record.key_values {
+            write!(self.out, " {}{}={}{}", dim, key, reset, value)?;
+        }
+        writeln!(self.out)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+}
+
+/// `portal_rCURRENT.log` -> Some("portal"), `portal_r2021-11-14_11-51-24.restart-0001.log` -> Some("portal")
+fn rotation_group(path: &Path) -> Option<(String, bool)> {
+    let stem = path.file_name()?.to_str()?.strip_suffix(".log")?;
+    let (name, infix) = stem.rsplit_once("_r")?;
+    let current = infix.eq_ignore_ascii_case("current");
+    (current || infix.starts_with(|c: char| c.is_ascii_digit())).then(|| (name.to_string(), current))
+}
+
+/// the files to read, oldest first, with true for the current ones
+fn expand(path: &Path) -> io::Result<Vec<(PathBuf, bool)>> {
+    let (dir, only) = if path.is_dir() {
+        (path.to_path_buf(), None)
+    } else {
+        match rotation_group(path) {
+            Some((name, true)) => (path.parent().unwrap_or(Path::new(".")).to_path_buf(), Some(name)),
+            _ => return Ok(vec![(path.to_path_buf(), true)]),
+        }
+    };
+    let dir = if dir.as_os_str().is_empty() { PathBuf::from(".") } else { dir };
+    // per group, the timestamps sort in time order, the current file comes last
+    let mut groups: BTreeMap<String, Vec<(PathBuf, bool)>> = BTreeMap::new();
+    let mut others = vec![];
+    for dir_entry in std::fs::read_dir(&dir)? {
+        let file = dir_entry?.path();
+        match rotation_group(&file) {
+            Some((name, current)) if only.as_ref().is_none_or(|only| *only == name) => {
+                groups.entry(name).or_default().push((file, current));
+            }
+            None if only.is_none() && file.extension().is_some_and(|ext| ext == "log") => others.push((file, true)),
+            _ => {}
+        }
+    }
+    for files in groups.values_mut() {
+        files.sort_by(|(a, a_current), (b, b_current)| a_current.cmp(b_current).then_with(|| a.cmp(b)));
+    }
+    others.sort();
+    Ok(groups.into_values().flatten().chain(others).collect())
+}
+
+/// A file read up to its end, reopened when it was rotated or truncated.
+struct Tail {
+    path: PathBuf,
+    reader: BufReader<File>,
+    pos: u64,
+    /// a line not ended yet
+    partial: String,
+}
+
+impl Tail {
+    fn open(path: &Path) -> io::Result<Self> {
+        Ok(Self { path: path.to_path_buf(), reader: BufReader::new(File::open(path)?), pos: 0, partial: String::new() })
+    }
+
+    fn read_lines(&mut self, printer: &mut Printer<impl Write>) -> io::Result<()> {
+        let mut buf = vec![];
+        loop {
+            buf.clear();
+            let n = self.reader.read_until(b'\n', &mut buf)?;
+            if n == 0 {
+                return Ok(());
+            }
+            self.pos += n as u64;
+            self.partial.push_str(&String::from_utf8_lossy(&buf));
+            if self.partial.ends_with('\n') {
+                printer.print_line(&std::mem::take(&mut self.partial))?;
+            }
+        }
+    }
+
+    /// read the new lines, from the start of the new file if it was rotated
+    fn poll(&mut self, printer: &mut Printer<impl Write>) -> io::Result<()> {
+        self.read_lines(printer)?;
+        let Ok(metadata) = std::fs::metadata(&self.path) else {
+            // moved away and not created again yet
+            return Ok(());
+        };
+        if metadata.len() < self.pos || !self.same_file(&metadata)? {
+            // the writer may have appended to the old file between the read above and the rotation
+            self.read_lines(printer)?;
+            if !self.partial.is_empty() {
+                printer.print_line(&std::mem::take(&mut self.partial))?;
+            }
+            *self = Self::open(&self.path)?;
+            self.read_lines(printer)?;
+        }
+        Ok(())
+    }
+
+    #[cfg(unix)]
+    fn same_file(&self, metadata: &std::fs::Metadata) -> io::Result<bool> {
+        use std::os::unix::fs::MetadataExt;
+        Ok(self.reader.get_ref().metadata()?.ino() == metadata.ino())
+    }
+
+    #[cfg(not(unix))]
+    fn same_file(&self, _metadata: &std::fs::Metadata) -> io::Result<bool> {
+        Ok(true)
+    }
+}
+
+fn read_all(reader: impl Read, printer: &mut Printer<impl Write>) -> io::Result<()> {
+    let mut reader = BufReader::new(reader);
+    let mut buf = vec![];
+    while reader.read_until(b'\n', &mut buf)? > 0 {
+        printer.print_line(&String::from_utf8_lossy(&buf))?;
+        buf.clear();
+    }
+    Ok(())
+}
+
+fn run(args: Args) -> io::Result<()> {
+    let colored = match args.color {
+        ColorChoice::Auto => color::enabled_for_stdout(),
+        ColorChoice::Always => true,
+        ColorChoice::Never => false,
+    };
+    let mut printer = Printer::new(io::BufWriter::new(io::stdout().lock()), Filter::from_args(&args), colored);
+    if args.paths.is_empty() || args.paths == [PathBuf::from("-")] {
+        // a pipe is followed until it is closed anyway
+        read_all(io::stdin().lock(), &mut printer)?;
+        return printer.flush();
+    }
+
+    let mut tails = vec![];
+    for path in &args.paths {
+        for (file, current) in expand(path)? {
+            if args.follow && current {
+                let mut tail = Tail::open(&file)?;
+                tail.read_lines(&mut printer)?;
+                tails.push(tail);
+            } else {
+                read_all(File::open(&file)?, &mut printer)?;
+            }
+        }
+    }
+    printer.flush()?;
+    while !tails.is_empty() {
+        std::thread::sleep(POLL_INTERVAL);
+        for tail in tails.iter_mut() {
+            tail.poll(&mut printer)?;
+        }
+        printer.flush()?;
+    }
+    Ok(())
+}
+
+fn main() {
+    match run(Args::parse()) {
+        Ok(()) => {}
+        // eg: piped into `head`
+        Err(err) if err.kind() == io::ErrorKind::BrokenPipe => {}
+        Err(err) => {
+            eprintln!("fblog-tail: {}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_printer() {
+        let lines = [
+            "[INFO] 2021-11-14 11:00:00.000000 +08:00 web-10.0.0.7 portal::http src/http.rs:1 started",
+            "[ERROR] 2021-11-14 11:30:00.000000 +08:00 web-10.0.0.7 portal::db src/db.rs:2 query failed",
+            "  caused by: timeout",
+            "[WARN] 2021-11-14 12:30:00.000000 +08:00 web-10.0.0.8 portal::db src/db.rs:3 slow",
+        ];
+        let print = |filter: Filter| {
+            let mut printer = Printer::new(vec![], filter, false);
+            for line in lines {
+                printer.print_line(line).unwrap();
+            }
+            String::from_utf8(printer.out).unwrap()
+        };
+        let filter = || Filter { level: None, modules: vec![], since: None, until: None, host: None, grep: None };
+
+        assert_eq!(print(Filter { level: Some(Level::Warn), modules: vec!["portal::db".to_string()], ..filter() }),
+                   "11-14 11:30:00.000 ERROR web portal::db src/db.rs:2 query failed\n  caused by: timeout\n\
+                    11-14 12:30:00.000 WARN  web portal::db src/db.rs:3 slow\n");
+        assert_eq!(print(Filter { since: parse_time("2021-11-14 11:10:00 +08:00").ok(), until: parse_time("2021-11-14T12:00:00+08:00").ok(), ..filter() }),
+                   "11-14 11:30:00.000 ERROR web portal::db src/db.rs:2 query failed\n  caused by: timeout\n");
+        assert_eq!(print(Filter { host: Some("10.0.0.8".to_string()), grep: Regex::new("slow|started").ok(), ..filter() }),
+                   "11-14 12:30:00.000 WARN  web portal::db src/db.rs:3 slow\n");
+        assert!(parse_time("2021-11-14").is_ok() && parse_time("yesterday").is_err());
+    }
+
+    #[test]
+    fn test_follow_rotation() {
+        let dir = std::env::temp_dir().join(format!("fblog-tail-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let line = |n: u32| format!("[INFO] 2021-11-14 11:00:00.000000 +08:00 web-10.0.0.7 portal src/main.rs:{} line {}\n", n, n);
+        std::fs::write(dir.join("portal_r2021-11-14_11-00-00.log"), line(1)).unwrap();
+        std::fs::write(dir.join("portal_r2021-11-13_11-00-00.log"), line(0)).unwrap();
+        std::fs::write(dir.join("portal_rCURRENT.log"), line(2)).unwrap();
+        std::fs::write(dir.join("other.txt"), "x").unwrap();
+
+        let files = expand(&dir.join("portal_rCURRENT.log")).unwrap();
+        let names: Vec<_> = files.iter().map(|(file, current)| (file.file_name().unwrap().to_str().unwrap(), *current)).collect();
+        assert_eq!(names, vec![
+            ("portal_r2021-11-13_11-00-00.log", false),
+            ("portal_r2021-11-14_11-00-00.log", false),
+            ("portal_rCURRENT.log", true),
+        ]);
+        assert_eq!(expand(&dir).unwrap(), files);
+
+        let mut printer = Printer::new(vec![], Filter::from_args(&Args::parse_from(["fblog-tail"])), false);
+        let mut tail = Tail::open(&dir.join("portal_rCURRENT.log")).unwrap();
+        tail.poll(&mut printer).unwrap();
+        // rotated: the current file is moved away and a new one is created,
+        // the writer appends a last line and a line without its newline to the old file before switching
+        std::fs::rename(dir.join("portal_rCURRENT.log"), dir.join("portal_r2021-11-15_11-00-00.log")).unwrap();
+        let mut old = std::fs::OpenOptions::new().append(true).open(dir.join("portal_r2021-11-15_11-00-00.log")).unwrap();
+        write!(old, "{}{}", line(4), line(5).trim_end()).unwrap();
+        std::fs::write(dir.join("portal_rCURRENT.log"), line(3)).unwrap();
+        tail.poll(&mut printer).unwrap();
+        let printed = String::from_utf8(printer.out).unwrap();
+        let lines: Vec<_> = printed.lines().skip_while(|line| !line.ends_with("line 2")).collect();
+        assert_eq!(lines, vec![
+            "11-14 11:00:00.000 INFO  web portal src/main.rs:2 line 2",
+            "11-14 11:00:00.000 INFO  web portal src/main.rs:4 line 4",
+            "11-14 11:00:00.000 INFO  web portal src/main.rs:5 line 5",
+            "11-14 11:00:00.000 INFO  web portal src/main.rs:3 line 3",
+        ]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
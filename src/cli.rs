@@ -0,0 +1,160 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+
+use clap::builder::BoolishValueParser;
+
+use crate::{LocalLog, ShutdownGuard, SysLog, SyslogFacility};
+
+/// The logging flags every service declares, to be flattened into its own clap arguments:
+/// ```ignore
+/// #[derive(clap::Parser)]
+/// struct Args {
+///     #[command(flatten)]
+///     log: fblog::LogArgs,
+/// }
+/// let _guard = Args::parse().log.start_logger(env!("CARGO_PKG_NAME"));
+/// ```
+/// Each flag falls back to an env var, eg: `FBLOG_LOG_SPEC=debug`, `FBLOG_LOG_UDP=true`.
+/// <br>
+/// The bool flags can be given alone (`--log-udp`) or with a value (`--log-file=false`).
+#[derive(Debug, Clone, clap::Args)]
+pub struct LogArgs {
+    /// log spec, eg: "info" or "info,h2=warn"
+    #[arg(long = "log-spec", env = "FBLOG_LOG_SPEC", default_value = "info")]
+    pub log_spec: String,
+
+    /// send the records to syslog over udp instead of console/file
+    #[arg(long = "log-udp", env = "FBLOG_LOG_UDP", default_value = "false", num_args = 0..=1, default_missing_value = "true",
+          value_parser = BoolishValueParser::new())]
+    pub log_udp: bool,
+
+    /// address of the syslog server, with --log-udp
+    #[arg(long = "log-udp-addr", env = "FBLOG_LOG_UDP_ADDR", default_value = "127.0.0.1:514")]
+    pub log_udp_addr: SocketAddr,
+
+    /// syslog facility, with --log-udp, eg: "local1"
+    #[arg(long = "log-facility", env = "FBLOG_LOG_FACILITY", default_value = "user")]
+    pub log_facility: SyslogFacility,
+
+    /// log to the console, without --log-udp
+    #[arg(long = "log-console", env = "FBLOG_LOG_CONSOLE", default_value = "true", num_args = 0..=1, default_missing_value = "true",
+          value_parser = BoolishValueParser::new())]
+    pub log_console: bool,
+
+    /// log to a file, without --log-udp
+    #[arg(long = "log-file", env = "FBLOG_LOG_FILE", default_value = "false", num_args = 0..=1, default_missing_value = "true",
+          value_parser = BoolishValueParser::new())]
+    pub log_file: bool,
+
+    /// directory of the log files, with --log-file, default: the current directory
+    #[arg(long = "log-dir", env = "FBLOG_LOG_DIR")]
+    pub log_dir: Option<PathBuf>,
+}
+
+impl LogArgs {
+    /// Some with --log-udp
+    pub fn sys_log(&self) -> Option<SysLog> {
+        self.log_udp.then(|| SysLog::new(self.log_facility.0, self.log_udp_addr))
+    }
+
+    /// Some without --log-udp, if the console or file log is enabled
+    pub fn local_log(&self) -> Option<LocalLog> {
+        if self.log_udp || (!self.log_console && !self.log_file) {
+            return None;
+        }
+        let local_log = LocalLog::new(self.log_console, self.log_file);
+        Some(match &self.log_dir {
+            Some(log_dir) => local_log.with_log_dir(log_dir),
+            None => local_log,
+        })
+    }
+
+    /// same as `start_logger_automatically` with these flags, panic if no log is enabled
+    pub fn start_logger(&self, process_name: &str) -> ShutdownGuard {
+        match (self.sys_log(), self.local_log()) {
+            (Some(sys_log), _) => sys_log.start_udp_logger(&self.log_spec, process_name),
+            (None, Some(local_log)) => local_log.start_local_logger(&self.log_spec),
+            (None, None) => panic!("Must enable at least 1 log: udp/console/file"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::process::Command;
+    use std::str::FromStr;
+
+    use clap::Parser;
+    use syslog::Facility;
+
+    use super::*;
+
+    #[derive(Parser)]
+    struct Args {
+        #[command(flatten)]
+        log: LogArgs,
+    }
+
+    const ENV_VARS: [&str; 7] = [
+        "FBLOG_LOG_SPEC", "FBLOG_LOG_UDP", "FBLOG_LOG_UDP_ADDR", "FBLOG_LOG_FACILITY", "FBLOG_LOG_CONSOLE", "FBLOG_LOG_FILE", "FBLOG_LOG_DIR",
+    ];
+
+    fn parse(args: &[&str]) -> Result<LogArgs, clap::Error> {
+        Args::try_parse_from(std::iter::once("portal").chain(args.iter().copied())).map(|args| args.log)
+    }
+
+    #[test]
+    fn test_log_args() {
+        let args = parse(&[]).unwrap();
+        assert_eq!((args.log_spec.as_str(), args.log_udp, args.log_console, args.log_file), ("info", false, true, false));
+
+        let args = parse(&["--log-spec", "debug,h2=warn", "--log-udp", "--log-udp-addr", "10.0.0.7:514", "--log-facility", "local1"]).unwrap();
+        assert_eq!(args.log_spec, "debug,h2=warn");
+        assert!(args.log_udp);
+        assert_eq!(args.log_udp_addr, SocketAddr::from_str("10.0.0.7:514").unwrap());
+        assert_eq!(args.log_facility, SyslogFacility(Facility::LOG_LOCAL1));
+        assert!(args.sys_log().is_some() && args.local_log().is_none());
+
+        let args = parse(&["--log-console=false", "--log-file", "--log-dir", "/tmp/portal"]).unwrap();
+        assert!(!args.log_udp && !args.log_console && args.log_file);
+        assert_eq!(args.log_dir, Some(PathBuf::from("/tmp/portal")));
+        assert!(args.sys_log().is_none() && args.local_log().is_some());
+
+        let args = parse(&["--log-console", "no"]).unwrap();
+        assert!(args.sys_log().is_none() && args.local_log().is_none());
+
+        assert!(parse(&["--log-facility", "local9"]).is_err());
+        assert!(parse(&["--log-udp-addr", "localhost"]).is_err());
+    }
+
+    /// the env vars are only set for a child process running `test_log_args_env_child`:
+    /// changing the env of this process would race with the other tests reading it, eg: NO_COLOR
+    #[test]
+    fn test_log_args_env() {
+        let mut child = Command::new(std::env::current_exe().unwrap());
+        for name in ENV_VARS {
+            child.env_remove(name);
+        }
+        let output = child
+            .env("FBLOG_LOG_FILE", "true")
+            .env("FBLOG_LOG_SPEC", "warn")
+            .args(["cli::test::test_log_args_env_child", "--exact", "--nocapture"])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success() && stdout.contains("1 passed"), "{}{}", stdout, String::from_utf8_lossy(&output.stderr));
+    }
+
+    #[test]
+    fn test_log_args_env_child() {
+        // nothing to check unless run by `test_log_args_env`
+        if std::env::var_os("FBLOG_LOG_FILE").is_none() {
+            return;
+        }
+        let args = parse(&[]).unwrap();
+        assert!(args.log_file && args.log_console);
+        assert_eq!(args.log_spec, "warn");
+        // the flag wins over the env var
+        assert!(!parse(&["--log-file=false"]).unwrap().log_file);
+    }
+}
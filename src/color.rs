@@ -0,0 +1,32 @@
+use std::io::IsTerminal;
+
+use log::Level;
+
+pub const RESET: &str = "\x1b[0m";
+pub const DIM: &str = "\x1b[2m";
+
+/// ansi style for the level tag and the message of a record
+pub fn level_style(level: Level) -> &'static str {
+    match level {
+        Level::Error => "\x1b[1;31m", // bold red
+        Level::Warn => "\x1b[33m",    // yellow
+        Level::Info => "\x1b[32m",    // green
+        Level::Debug => "\x1b[36m",   // cyan
+        Level::Trace => "\x1b[35m",   // magenta
+    }
+}
+
+/// colors are disabled if NO_COLOR is set (to any value), see https://no-color.org
+pub fn no_color() -> bool {
+    std::env::var_os("NO_COLOR").is_some()
+}
+
+/// true if stdout is a terminal and NO_COLOR is not set
+pub fn enabled_for_stdout() -> bool {
+    !no_color() && std::io::stdout().is_terminal()
+}
+
+/// true if stderr is a terminal and NO_COLOR is not set
+pub fn enabled_for_stderr() -> bool {
+    !no_color() && std::io::stderr().is_terminal()
+}
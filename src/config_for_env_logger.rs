@@ -1,119 +1,200 @@
 use std::io::Write;
+use std::sync::Arc;
 
-use env_logger::Builder;
+use env_logger::{Builder, WriteStyle};
+use flexi_logger::{LogSpecBuilder, LogSpecification};
+use log::{LevelFilter, Log, Metadata, Record};
 
-// lazy_static::lazy_static! {
-//         static ref LOCAL_HOST_NAME: String = "".to_string();
-//         static ref LOCAL_IP: String = "".to_string();
-//         // static ref LOCAL_HOST_NAME: String = crate::hostname();
-//         // static ref LOCAL_IP: String = crate::get_proper_ip();
-//         // static ref l
-//     }
-// use once_cell::sync::OnceCell;
-// static LOCAL_HOST_NAME: OnceCell<String> = OnceCell::new();
-// static LOCAL_IP: OnceCell<String> = OnceCell::new();
-
-// const log_record_format_relative: &str = "[{}] {} {}:{} {}:{} {}";
-// const log_record_format_absolute: &str = "[{}] {} {}:{} file://{}:{} {}";
+use crate::config_for_flexi_logger::{max_level_of, SpecOverride};
+use crate::control::{Backend, SinkCounters, SinkStats};
+use crate::key_values::{AsJsonFields, JsonEscaped};
+use crate::template::Part;
 
 // log_filters specification is conformed to rust log-specification which can be: debug, xx_module=xxx
+/// every line is laid out by the global template, see `template::init`,
+/// and colored by level if the target of the builder (stderr by default) is a terminal and NO_COLOR is not set.
 pub fn get_default_env_logger_builder(log_filters: &str) -> Builder {
-    // let local_host_name = LOCAL_HOST_NAME.get_or_init(||crate::hostname());
-    // let local_ip = LOCAL_IP.get_or_init(||crate::get_proper_ip());
-    //
+    let template = crate::template::global();
+
+    let mut builder = env_logger::Builder::new();
+    builder.format(move |buf, record| {
+        // the styles write nothing unless the target of the builder is colored, see `Builder::write_style`
+        let level_style = buf.default_level_style(record.level());
+        let mut dimmed = buf.style();
+        dimmed.set_dimmed(true);
+        template.write_styled(buf, record, &mut |w, part, text| {
+            let style = match part {
+                Part::Level => &level_style,
+                Part::Other => &dimmed,
+            };
+            write!(w, "{}", style.value(text))
+        })?;
+        writeln!(buf)
+    }).parse_filters(log_filters);
+    if crate::color::no_color() {
+        builder.write_style(WriteStyle::Never);
+    }
+    builder
+}
+
+/// same as get_default_env_logger_builder, but every record is written as one JSON object per line,
+/// with key-values of the record as additional fields, eg:
+/// {"level":"INFO","time":"...","host":"...","ip":"...","module":"...","file":"...","line":12,"msg":"joined","user_id":42}
+pub fn get_json_env_logger_builder(log_filters: &str) -> Builder {
     let local_host_name = crate::hostname();
     let local_ip = crate::get_proper_ip();
 
     let mut builder = env_logger::Builder::new();
     builder.format(move |buf, record| {
-
-        // 1. if file path is relative, nothing changes.
-        // 2. if it is absolute, prefixed with "file://"
-        // // **************************************************
-        // // low_todo: string/memory optimization
-        // // * solution-a:
-        // //   use 2 writeln!, 1st is for relative path, 2nd is for absolute.
-        // //   * good:
-        // //     more performative
-        // //   * bad:
-        // //     more redundant code
-        // // * solution-b:
-        // //   use 1 writeln!, but return 2 static "[{}] {} {}:{} {}:{} {}" according to property: its relative/absolute
-        // //   (might use many escape, eg: {{ }})
-        // //   stuck(2021-10-19T16:07:13.381Z): the 1st parameter of 'format!', 'write!' must be a literal (determined by macro)
-        // //   return to solution-a:
-        // // **************************************************
-        // let log_record_format = if record.file().unwrap_or("").starts_with("/") {
-        //     log_record_format_absolute
-        // } else {log_record_format_relative};
-        // writeln!(buf, log_record_format,
-        //          record.level(),
-        //          chrono::Local::now(),
-        //
-        //          // eg: portal::oauth2::http:205
-        //          record.module_path().unwrap_or_default(),
-        //          record.line().unwrap_or(0),
-        //
-        //          // files relative to project will show in relative format: eg: src/oauth2/http.rs:205
-        //          // or show in absolute format: /absolute-path/rust/cargo/registry/src/github.com-1ecc6299db9ec823/actix-web-3.3.2/src/middleware/logger.rs:337
-        //          record.file().unwrap_or(""),
-        //          file_path,
-        //          record.args())
-
-        let file_path = record
-            .file()
-            .map(|p| if p.starts_with("/") { "file://".to_string() + p } else { p.to_string() })
-            .unwrap_or("".to_string());
-        writeln!(buf, "[{}] {} {}-{} {}:{} {}:{} {}",
+        writeln!(buf, r#"{{"level":"{}","time":"{}","host":"{}","ip":"{}","module":"{}","file":"{}","line":{},"msg":"{}"{}}}"#,
                  record.level(),
                  chrono::Local::now(),
-                 local_host_name,
-                 local_ip,
-
-                 // eg: portal::oauth2::http:205
-                 record.module_path().unwrap_or_default(),
-                 record.line().unwrap_or(0),
-                 file_path,
+                 JsonEscaped(&local_host_name),
+                 JsonEscaped(&local_ip),
+                 JsonEscaped(record.module_path().unwrap_or_default()),
+                 JsonEscaped(record.file().unwrap_or_default()),
                  record.line().unwrap_or(0),
-                 record.args())
+                 JsonEscaped(&record.args().to_string()),
+                 AsJsonFields(record.key_values()))
     }).parse_filters(log_filters);
     builder
 }
 
+/// same as `builder.init()`, with the records going through `pipeline` first
+pub fn init(builder: Builder) {
+    try_init(builder).expect("env_logger::init should not be called after logger initialized");
+}
+
+/// same as `builder.try_init()`, with the records going through `pipeline` first.
+/// <br>
+/// Replaces the logger started before (if any), and is registered in `control::handle()`:
+/// a spec set at runtime can only restrict what the filters of the builder enable.
+pub fn try_init(mut builder: Builder) -> Result<(), log::SetLoggerError> {
+    let logger = EnvLogger {
+        inner: builder.build(),
+        spec_override: SpecOverride::default(),
+        counters: Arc::default(),
+    };
+    let max_level = logger.inner.filter();
+    let control = EnvLoggerControl {
+        // the filters of env_logger can't be read back, only their max level
+        configured: LogSpecBuilder::new().default(max_level).build(),
+        max_level,
+        spec_override: logger.spec_override.clone(),
+        counters: logger.counters.clone(),
+    };
+    crate::pipeline::set_boxed_logger(Box::new(logger)).map(|()| crate::pipeline::set_max_level(max_level))?;
+    crate::control::handle().register(&max_level.to_string().to_lowercase(), Box::new(control));
+    Ok(())
+}
+
+/// An env_logger with the spec set at runtime on top of its own filters.
+struct EnvLogger {
+    inner: env_logger::Logger,
+    spec_override: SpecOverride,
+    counters: Arc<SinkCounters>,
+}
+
+impl Log for EnvLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        self.inner.enabled(metadata)
+            && match self.spec_override.read() {
+                Ok(spec_override) => spec_override.as_ref().is_none_or(|spec| spec.enabled(metadata.level(), metadata.target())),
+                Err(_) => true,
+            }
+    }
+
+    fn log(&self, record: &Record) {
+        // the records of the ring buffer skip the runtime spec, the filters of env_logger still apply
+        if (self.enabled(record.metadata()) || crate::pipeline::is_replaying()) && self.inner.matches(record) {
+            self.inner.log(record);
+            self.counters.count(true);
+        }
+    }
+
+    fn flush(&self) {
+        self.inner.flush();
+    }
+}
+
+/// Lets `control::handle()` restrict the spec of a started env_logger.
+struct EnvLoggerControl {
+    configured: LogSpecification,
+    /// the max level of the filters of env_logger
+    max_level: LevelFilter,
+    spec_override: SpecOverride,
+    counters: Arc<SinkCounters>,
+}
+
+impl Backend for EnvLoggerControl {
+    fn set_spec(&mut self, spec: Option<&LogSpecification>) {
+        let max_level = spec.map_or(self.max_level, |spec| max_level_of(spec).min(self.max_level));
+        if let Ok(mut spec_override) = self.spec_override.write() {
+            *spec_override = spec.cloned();
+        }
+        crate::pipeline::set_max_level(max_level);
+    }
+
+    fn configured_spec(&self) -> &LogSpecification {
+        &self.configured
+    }
+
+    fn sinks(&self) -> Vec<SinkStats> {
+        let spec = match self.spec_override.read() {
+            Ok(spec_override) => spec_override.as_ref().map(|spec| spec.to_string()),
+            Err(_) => None,
+        };
+        vec![self.counters.stats("env_logger", &spec.unwrap_or_else(|| self.configured.to_string()))]
+    }
+}
 
 pub fn init_default_env_logger(log_filters: &str) {
-    get_default_env_logger_builder(log_filters).init()
+    init(get_default_env_logger_builder(log_filters))
 }
 
 // set log level to "debug", convenient for test env
 pub fn init_default_env_logger_with_debug_level() {
-    get_default_env_logger_builder("debug").init()
+    init(get_default_env_logger_builder("debug"))
 }
 
 
 // set log level to "info", convenient for test env
 pub fn init_default_env_logger_with_info_level() {
-    get_default_env_logger_builder("info").init()
+    init(get_default_env_logger_builder("info"))
 }
 
 // set log level to "warn", convenient for test env
 pub fn init_default_env_logger_with_warn_level() {
-    get_default_env_logger_builder("warn").init()
+    init(get_default_env_logger_builder("warn"))
 }
 
 
 // set log level to "error", convenient for test env
 pub fn init_default_env_logger_with_error_level() {
-    get_default_env_logger_builder("error").init()
+    init(get_default_env_logger_builder("error"))
 }
 
 #[test]
 fn test_get_default_env_logger_builder() {
     use log::*;
-    let mut builder = get_default_env_logger_builder("debug");
-    builder.init();
+    let _lock = crate::test_helper::logger_lock();
+    let builder = get_default_env_logger_builder("debug");
+    init(builder);
     debug!("hello");
     info!("hello");
     warn!("hello");
     error!("hello");
+
+    let handle = crate::control::handle();
+    assert_eq!(handle.current_spec().as_deref(), Some("debug"));
+    let debug = Metadata::builder().level(Level::Debug).target("portal").build();
+    assert!(log::logger().enabled(&debug));
+    handle.set_spec("info").unwrap();
+    assert!(!log::logger().enabled(&debug));
+    // can't log more than the filters of env_logger
+    handle.set_spec("trace").unwrap();
+    assert!(!log::logger().enabled(&Metadata::builder().level(Level::Trace).target("portal").build()));
+    handle.reset_spec().unwrap();
+    assert!(log::logger().enabled(&debug));
+    assert_eq!(handle.sinks().unwrap()[0].name, "env_logger");
 }
\ No newline at end of file
@@ -1,111 +1,694 @@
-use flexi_logger::{Age, Cleanup, Criterion, DeferredNow, Duplicate, FileSpec, Logger, Naming};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+
+use flexi_logger::{Age, Cleanup, Criterion, DeferredNow, FileSpec, Logger, LoggerHandle, LogSpecBuilder, LogSpecification, Naming};
+use flexi_logger::writers::{FileLogWriter, LogWriter};
 use log::*;
-use time::macros::offset;
+use time::UtcOffset;
+
+use crate::control::{Backend, SinkCounters, SinkStats};
+use crate::key_values::{AsJsonFields, JsonEscaped};
+use crate::syslog_codes::covers;
 
 // const TS_S: &str = "[year]-[month]-[day]T[hour]:[minute]:[second].[subsecond digits:6][offset_hour sign:mandatory]";
 
 
-/// A logline-formatter that produces log lines like
+/// A logline-formatter that lays out every line with the global template (see `template::init`),
+/// by default producing log lines like
 /// <br>
-/// ```[2016-01-13 15:25:01.640870 +01:00] INFO [foo::bar] src/foo/bar.rs:26: Task successfully read from conf.json```
+/// ```[INFO] 2016-01-13 15:25:01.640870 +08:00 host-10.0.0.1 foo::bar src/foo/bar.rs:26 Task successfully read from conf.json user_id=42```
 /// <br>
-/// i.e. with timestamp, module path and file location.
+/// i.e. with timestamp, host, module path, file location and the record's key-values (if any).
 ///
 /// # Errors
 ///
 /// See `std::write`
 pub fn detailed_format(
     w: &mut dyn std::io::Write,
-    now: &mut DeferredNow,
+    _now: &mut DeferredNow,
     record: &Record,
 ) -> Result<(), std::io::Error> {
-    let local_host_name = crate::hostname();
-    let local_ip = crate::get_proper_ip();
-    let line_number = record.line().unwrap_or(0);
-
-
-    let file_path = record
-        .file()
-        .map(|p| if p.starts_with("/") { "file://".to_string() + p } else { p.to_string() })
-        .unwrap_or("<unnamed>".to_string());
+    crate::template::global().write(w, record)
+}
 
+/// A logline-formatter that produces one JSON object per line, like
+/// <br>
+/// ```{"level":"INFO","time":"2016-01-13 15:25:01.640870 +08:00:00","host":"host","ip":"10.0.0.1","module":"foo::bar","file":"src/foo/bar.rs","line":26,"msg":"joined","user_id":42}```
+/// the record's key-values are added as fields after "msg", the time is at the offset of the global template, see `Template::with_offset`.
+/// the record's key-values are added as fields after "msg".
+///
+/// # Errors
+///
+/// See `std::write`
+pub fn json_format(
+    w: &mut dyn std::io::Write,
+    now: &mut DeferredNow,
+    record: &Record,
+) -> Result<(), std::io::Error> {
     write!(
         w,
-        "[{}] {} {}-{} {}:{} {}:{} {}",
-        // now.now().to_offset(offset!(+8)).format(&TS_S).unwrap_or_default(),
+        r#"{{"level":"{}","time":"{}","host":"{}","ip":"{}","module":"{}","file":"{}","line":{},"msg":"{}"{}}}"#,
         record.level(),
-        now.now().to_offset(offset!(+8)),
-        local_host_name,
-        local_ip,
-        record.module_path().unwrap_or("<unnamed>"),
-        line_number,
-        file_path,
+        now.now().to_offset(template_offset()),
+        JsonEscaped(&crate::hostname()),
+        JsonEscaped(&crate::get_proper_ip()),
+        JsonEscaped(record.module_path().unwrap_or("<unnamed>")),
+        JsonEscaped(record.file().unwrap_or("<unnamed>")),
         record.line().unwrap_or(0),
-        &record.args()
+        JsonEscaped(&record.args().to_string()),
+        AsJsonFields(record.key_values())
     )
 }
 
-// pub fn colored_detailed_format(
-//     w: &mut dyn std::io::Write,
-//     now: &mut DeferredNow,
-//     record: &Record,
-// ) -> Result<(), std::io::Error> {
-//     let file_path = record
-//         .file()
-//         .map(|p| if p.starts_with("/") { "file://".to_string() + p } else { p.to_string() })
-//         .unwrap_or("<unnamed>".to_string());
-//
-//     let level = record.level();
-//     write!(
-//         w,
-//         "[{}] {} [{}] {}:{}: {}",
-//         style(level).paint(now.now().to_offset(offset!(+8)).to_string()),
-//         style(level).paint(record.level().to_string()),
-//         record.module_path().unwrap_or("<unnamed>"),
-//         file_path,
-//         record.line().unwrap_or(0),
-//         style(level).paint(&record.args().to_string())
-//     )
-// }
+/// the offset of the global template, for the times not written by the template
+fn template_offset() -> UtcOffset {
+    UtcOffset::from_whole_seconds(crate::template::global().offset().local_minus_utc()).unwrap_or(UtcOffset::UTC)
+}
 
-/// note:
-/// 1. Current log will always output to File1: ${package_name}_rCurrent.log, eg: buff_rCurrent.log
-/// 2. Additionally, (an empty file) File2: ${package_name}_r<Datetime-the-program-started>.log is also created. eg: buff_r2011-11-14T11:51:24+08.log.
-///    At every new day (00:00), all logs in File1 will move to File2, then File1 is truncated and served as current logging file.
-///    Thus, File2 includes the last day's log.
-///    eg: buff_r2011-11-14T11:51:24+08.log has logs from 2011-11-14T11:51:24+08.log to 2011-11-14T59:59:59+08.log
+/// Same as `detailed_format`, but with the level tag and message colored by level and the other fields dimmed.
+/// <br>
+/// Only meant for the console, `default_logger` uses it when the console is a terminal and NO_COLOR is not set.
 ///
-pub fn default_logger(log_spec: &str, log_to_stdout: bool, log_to_file: bool) -> Logger {
-    let mut logger = Logger::try_with_str(log_spec)
+/// # Errors
+///
+/// See `std::write`
+pub fn colored_detailed_format(
+    w: &mut dyn std::io::Write,
+    _now: &mut DeferredNow,
+    record: &Record,
+) -> Result<(), std::io::Error> {
+    crate::template::global().write_colored(w, record)
+}
+
+/// Writes to the console, records at or above `stderr_level` go to stderr, the others to stdout.
+/// <br>
+/// Lines are laid out by the global template, and colored if the stream is a terminal and NO_COLOR is not set.
+pub struct ConsoleWriter {
+    stderr_level: LevelFilter,
+    colored_stdout: bool,
+    colored_stderr: bool,
+}
+
+impl ConsoleWriter {
+    /// `stderr_level`: eg: LevelFilter::Error to route errors to stderr, LevelFilter::Off to write everything to stdout.
+    pub fn new(stderr_level: LevelFilter) -> Self {
+        Self {
+            stderr_level,
+            colored_stdout: crate::color::enabled_for_stdout(),
+            colored_stderr: crate::color::enabled_for_stderr(),
+        }
+    }
+
+    fn write_line(w: &mut dyn std::io::Write, colored: bool, record: &Record) -> std::io::Result<()> {
+        let template = crate::template::global();
+        if colored {
+            template.write_colored(w, record)?;
+        } else {
+            template.write(w, record)?;
+        }
+        writeln!(w)
+    }
+}
+
+impl LogWriter for ConsoleWriter {
+    fn write(&self, _now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        if record.level() <= self.stderr_level {
+            Self::write_line(&mut std::io::stderr().lock(), self.colored_stderr, record)
+        } else {
+            Self::write_line(&mut std::io::stdout().lock(), self.colored_stdout, record)
+        }
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        std::io::stdout().flush()?;
+        std::io::stderr().flush()
+    }
+}
+
+/// A spec replacing the own specs of several FilteredWriters while it is Some,
+/// used to change the level of all sinks at runtime.
+pub type SpecOverride = Arc<RwLock<Option<LogSpecification>>>;
+
+/// A LogWriter that only passes on the records enabled by its own spec,
+/// so that each sink of a logger can have its own level, eg: file at "debug", console at "warn".
+/// <br>
+/// The textfilter of the spec (eg: "info/timeout") applies too: only the records whose message matches it pass.
+/// <br>
+/// The records of the ring buffer (see `pipeline::set_ring_buffer`) pass whatever the spec.
+pub struct FilteredWriter<W: LogWriter> {
+    spec: LogSpecification,
+    spec_override: SpecOverride,
+    inner: W,
+}
+
+impl<W: LogWriter> FilteredWriter<W> {
+    pub fn new(spec: LogSpecification, inner: W) -> Self {
+        Self { spec, spec_override: SpecOverride::default(), inner }
+    }
+
+    /// use the spec of `spec_override` instead of the own one while it is set
+    pub fn with_spec_override(mut self, spec_override: SpecOverride) -> Self {
+        self.spec_override = spec_override;
+        self
+    }
+
+    fn enabled(&self, record: &Record) -> bool {
+        let enabled = |spec: &LogSpecification| {
+            spec.enabled(record.level(), record.target())
+                && spec.text_filter().is_none_or(|filter| filter.is_match(&record.args().to_string()))
+        };
+        match self.spec_override.read() {
+            Ok(spec_override) => enabled(spec_override.as_ref().unwrap_or(&self.spec)),
+            Err(_) => enabled(&self.spec),
+        }
+    }
+}
+
+impl<W: LogWriter> LogWriter for FilteredWriter<W> {
+    fn write(&self, now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        if crate::pipeline::is_replaying() || self.enabled(record) {
+            self.inner.write(now, record)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn max_log_level(&self) -> LevelFilter {
+        match self.spec_override.read() {
+            Ok(spec_override) => max_level_of(spec_override.as_ref().unwrap_or(&self.spec)),
+            Err(_) => max_level_of(&self.spec),
+        }
+    }
+
+    fn shutdown(&self) {
+        self.inner.shutdown()
+    }
+}
+
+/// A LogWriter counting the records written by `inner`, see `control::ControlHandle::sinks`.
+pub struct CountingWriter<W: LogWriter> {
+    counters: Arc<SinkCounters>,
+    inner: W,
+}
+
+impl<W: LogWriter> CountingWriter<W> {
+    pub fn new(counters: Arc<SinkCounters>, inner: W) -> Self {
+        Self { counters, inner }
+    }
+}
+
+impl<W: LogWriter> LogWriter for CountingWriter<W> {
+    fn write(&self, now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        let result = self.inner.write(now, record);
+        self.counters.count(result.is_ok());
+        result
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn max_log_level(&self) -> LevelFilter {
+        self.inner.max_log_level()
+    }
+
+    fn shutdown(&self) {
+        self.inner.shutdown()
+    }
+}
+
+/// Writes every record to all its writers.
+/// <br>
+/// Clones share the same writers.
+#[derive(Clone)]
+pub struct MultiWriter {
+    writers: Arc<Vec<Box<dyn LogWriter>>>,
+}
+
+impl MultiWriter {
+    pub fn new(writers: Vec<Box<dyn LogWriter>>) -> Self {
+        Self { writers: Arc::new(writers) }
+    }
+}
+
+impl LogWriter for MultiWriter {
+    fn write(&self, now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        // keep writing to the other sinks if one fails, report the last error
+        let mut result = Ok(());
+        for writer in self.writers.iter() {
+            if let Err(err) = writer.write(now, record) {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        let mut result = Ok(());
+        for writer in self.writers.iter() {
+            if let Err(err) = writer.flush() {
+                result = Err(err);
+            }
+        }
+        result
+    }
+
+    fn max_log_level(&self) -> LevelFilter {
+        self.writers.iter().map(|w| w.max_log_level()).max().unwrap_or(LevelFilter::Off)
+    }
+
+    fn shutdown(&self) {
+        for writer in self.writers.iter() {
+            writer.shutdown();
+        }
+    }
+}
+
+/// Routes some records of the file sink to their own file next to the main log file:
+/// ${package_name}_${name}_rCURRENT.log, eg: buff_audit_rCURRENT.log
+/// <br>
+/// A record is routed if its target starts with one of `targets`, or its level is at or above `level`.
+#[derive(Debug, Clone)]
+pub struct FileRoute {
+    pub name: String,
+    /// eg: "audit" for `info!(target: "audit", ...)`
+    pub targets: Vec<String>,
+    /// eg: LevelFilter::Error, LevelFilter::Off to route by target only
+    pub level: LevelFilter,
+    /// also write the routed records to the main log file
+    pub also_in_main_log: bool,
+    /// None: rotate like the main log file
+    pub rotation: Option<(Criterion, Naming, Cleanup)>,
+}
+
+impl FileRoute {
+    /// route records whose target starts with one of `targets` to the file named by `name`
+    pub fn for_targets(name: &str, targets: &[&str]) -> Self {
+        Self {
+            name: name.to_string(),
+            targets: targets.iter().map(|t| t.to_string()).collect(),
+            level: LevelFilter::Off,
+            also_in_main_log: false,
+            rotation: None,
+        }
+    }
+
+    /// route records at or above `level` to the file named by `name`
+    pub fn for_level(name: &str, level: LevelFilter) -> Self {
+        Self {
+            level,
+            ..Self::for_targets(name, &[])
+        }
+    }
+
+    pub fn also_in_main_log(mut self) -> Self {
+        self.also_in_main_log = true;
+        self
+    }
+
+    pub fn rotate(mut self, criterion: Criterion, naming: Naming, cleanup: Cleanup) -> Self {
+        self.rotation = Some((criterion, naming, cleanup));
+        self
+    }
+
+    pub fn matches(&self, record: &Record) -> bool {
+        record.level() <= self.level || self.targets.iter().any(|t| covers(t, record.target()))
+    }
+}
+
+/// The file sink of a local logger: the main log file plus the files of its routes.
+/// <br>
+/// Clones share the same files, so a clone can be kept to reopen them while the logger owns the other one.
+#[derive(Clone)]
+pub struct RoutingFileWriter {
+    main: Arc<FileLogWriter>,
+    routes: Arc<Vec<(FileRoute, FileLogWriter)>>,
+}
+
+impl RoutingFileWriter {
+    /// the files are in the current directory
+    pub fn new(routes: &[FileRoute]) -> Self {
+        Self::in_directory(None, routes)
+    }
+
+    /// the files are in `directory`, or in the current directory if None
+    pub fn in_directory(directory: Option<&Path>, routes: &[FileRoute]) -> Self {
+        let file_spec = || match directory {
+            Some(directory) => FileSpec::default().directory(directory),
+            None => FileSpec::default(),
+        };
+        Self {
+            main: Arc::new(file_log_writer(file_spec(), default_rotation())),
+            routes: Arc::new(routes
+                .iter()
+                .map(|route| {
+                    let file_spec = file_spec().discriminant(route.name.as_str());
+                    (route.clone(), file_log_writer(file_spec, route.rotation.unwrap_or_else(default_rotation)))
+                })
+                .collect()),
+        }
+    }
+
+    /// reopen the main log file and the files of the routes, eg: after logrotate moved them away
+    pub fn reopen_outputfile(&self) -> Result<(), flexi_logger::FlexiLoggerError> {
+        for writer in self.writers() {
+            writer.reopen_outputfile()?;
+        }
+        Ok(())
+    }
+
+    fn writers(&self) -> impl Iterator<Item=&FileLogWriter> {
+        std::iter::once(self.main.as_ref()).chain(self.routes.iter().map(|(_, w)| w))
+    }
+}
+
+impl LogWriter for RoutingFileWriter {
+    fn write(&self, now: &mut DeferredNow, record: &Record) -> std::io::Result<()> {
+        let mut routed = false;
+        let mut in_main_log = false;
+        for (route, writer) in self.routes.iter().filter(|(route, _)| route.matches(record)) {
+            routed = true;
+            in_main_log |= route.also_in_main_log;
+            writer.write(now, record)?;
+        }
+        if !routed || in_main_log {
+            self.main.write(now, record)?;
+        }
+        Ok(())
+    }
+
+    fn flush(&self) -> std::io::Result<()> {
+        for writer in self.writers() {
+            writer.flush()?;
+        }
+        Ok(())
+    }
+
+    fn shutdown(&self) {
+        for writer in self.writers() {
+            writer.shutdown();
+        }
+    }
+}
+
+/// The sinks of a local logger, each with its own log spec.
+/// <br>
+/// `None` disables the sink.
+#[derive(Debug, Clone)]
+pub struct LocalSinks {
+    pub console_log_spec: Option<String>,
+    pub file_log_spec: Option<String>,
+    /// records at or above this level are written to stderr instead of stdout, LevelFilter::Off for none.
+    pub stderr_level: LevelFilter,
+    /// only used if the file sink is enabled
+    pub file_routes: Vec<FileRoute>,
+    /// the directory of the log files, None for the current directory
+    pub log_dir: Option<PathBuf>,
+}
+
+impl LocalSinks {
+    /// both enabled sinks use the same spec, everything goes to stdout
+    pub fn new(log_spec: &str, log_to_stdout: bool, log_to_file: bool) -> Self {
+        Self {
+            console_log_spec: if log_to_stdout { Some(log_spec.to_string()) } else { None },
+            file_log_spec: if log_to_file { Some(log_spec.to_string()) } else { None },
+            stderr_level: LevelFilter::Off,
+            file_routes: vec![],
+            log_dir: None,
+        }
+    }
+
+    /// eg: "debug" if all sinks use "debug", or "console: warn; file: debug"
+    pub fn describe_log_specs(&self) -> String {
+        let specs: Vec<(&str, String)> = [("console", &self.console_log_spec), ("file", &self.file_log_spec)]
+            .iter()
+            .filter_map(|(sink, spec)| spec.as_ref().map(|spec| (*sink, spec.to_string())))
+            .collect();
+        describe_specs(&specs)
+    }
+}
+
+/// the spec shared by all sinks, or the spec of each one: "console: warn; file: debug"
+fn describe_specs(specs: &[(&str, String)]) -> String {
+    if specs.windows(2).all(|w| w[0].1 == w[1].1) {
+        specs.first().map(|(_, spec)| spec.to_string()).unwrap_or_default()
+    } else {
+        specs.iter().map(|(sink, spec)| format!("{}: {}", sink, spec)).collect::<Vec<_>>().join("; ")
+    }
+}
+
+fn parse_log_spec(log_spec: &str) -> LogSpecification {
+    LogSpecification::parse(log_spec)
         .map_err(|err| {
             println!("could not init logger with spec: {}, err: {:?}", log_spec, err);
             err
         })
         .expect("could not init logger")
+}
+
+/// the most verbose level a spec enables for any module
+pub fn max_level_of(spec: &LogSpecification) -> LevelFilter {
+    spec.module_filters().iter().map(|f| f.level_filter).max().unwrap_or(LevelFilter::Off)
+}
+
+/// the level a spec enables for `module`, ie: the level of the longest module filter matching it.
+fn level_for_module(spec: &LogSpecification, module: &str) -> LevelFilter {
+    spec.module_filters()
+        .iter()
+        .filter(|f| f.module_name.as_ref().map(|name| covers(name, module)).unwrap_or(true))
+        .max_by_key(|f| f.module_name.as_ref().map(String::len))
+        .map(|f| f.level_filter)
+        .unwrap_or(LevelFilter::Off)
+}
+
+/// the smallest spec enabling everything that any of `specs` enables.
+/// <br>
+/// The textfilter is kept if all specs have the same one, dropped otherwise (each sink still applies its own).
+pub fn union_of_log_specs(specs: &[&LogSpecification]) -> LogSpecification {
+    let mut builder = LogSpecBuilder::new();
+    builder.default(specs.iter().map(|spec| level_for_module(spec, "")).max().unwrap_or(LevelFilter::Off));
+    for spec in specs {
+        for module in spec.module_filters().iter().filter_map(|f| f.module_name.as_ref()) {
+            builder.module(module, specs.iter().map(|spec| level_for_module(spec, module)).max().unwrap_or(LevelFilter::Off));
+        }
+    }
+    let text_filter = |spec: &&LogSpecification| spec.text_filter().map(|filter| filter.as_str().to_string());
+    let shared_text_filter = match specs.first().map(text_filter) {
+        Some(first) if specs.iter().all(|spec| text_filter(spec) == first) => specs[0].text_filter().cloned(),
+        _ => None,
+    };
+    builder.build_with_textfilter(shared_text_filter)
+}
+
+/// rotate at every new day (+08:00), see `default_logger` for the naming of the files.
+pub fn default_rotation() -> (Criterion, Naming, Cleanup) {
+    (
+        Criterion::Age(Age::new_with_splitting_at_every_new_day_by_offset_hour(8)),
+        Naming::Timestamps(time::UtcOffset::from_hms(8, 0, 0).unwrap()),          // - let the rotated files have a timestamp in their name
+        Cleanup::KeepLogFiles(1024),    // - keep at most 1024 log files
+    )
+}
+
+fn file_log_writer(file_spec: FileSpec, (criterion, naming, cleanup): (Criterion, Naming, Cleanup)) -> FileLogWriter {
+    FileLogWriter::builder(file_spec)
+        .format(detailed_format)
+        .print_message()
+        .rotate(criterion, naming, cleanup)
+        .try_build()
+        .map_err(|err| {
+            println!("could not create log file, err: {:?}", err);
+            err
+        })
+        .expect("could not create log file")
+}
+
+/// Every enabled sink of `sinks` gets records according to its own spec,
+/// the spec of the returned Logger is the union of them.
+/// <br>
+/// The log file is rotated in the same way as `default_logger`, records matching a FileRoute go to the route's file.
+pub fn local_logger(sinks: &LocalSinks) -> Logger {
+    build_local_logger(sinks).0
+}
+
+/// What `control::handle()` needs of the sinks of a local logger.
+struct LocalSinkControls {
+    /// the union of the specs of the sinks
+    configured: LogSpecification,
+    /// the file sink, if enabled
+    file_writer: Option<RoutingFileWriter>,
+    /// the writers of all sinks, as given to the Logger
+    writer: MultiWriter,
+    /// each enabled sink
+    sinks: Vec<SinkControl>,
+}
+
+/// What `control::handle()` needs of a sink of a local logger.
+struct SinkControl {
+    name: &'static str,
+    /// the spec the sink was started with
+    spec: LogSpecification,
+    /// the spec set at runtime, given to the FilteredWriter of the sink
+    spec_override: SpecOverride,
+    counters: Arc<SinkCounters>,
+}
+
+impl SinkControl {
+    fn new(name: &'static str, spec: &LogSpecification) -> Self {
+        Self { name, spec: spec.clone(), spec_override: SpecOverride::default(), counters: Arc::new(SinkCounters::default()) }
+    }
+
+    fn effective_spec(&self) -> LogSpecification {
+        match self.spec_override.read() {
+            Ok(spec_override) => spec_override.as_ref().unwrap_or(&self.spec).clone(),
+            Err(_) => self.spec.clone(),
+        }
+    }
+
+    fn set_spec_override(&self, spec: Option<LogSpecification>) {
+        if let Ok(mut spec_override) = self.spec_override.write() {
+            *spec_override = spec;
+        }
+    }
+}
+
+fn build_local_logger(sinks: &LocalSinks) -> (Logger, LocalSinkControls) {
+    let console_log_spec = sinks.console_log_spec.as_deref().map(parse_log_spec);
+    let file_log_spec = sinks.file_log_spec.as_deref().map(parse_log_spec);
+
+    let mut writers: Vec<Box<dyn LogWriter>> = vec![];
+    let mut sink_controls = vec![];
+    if let Some(spec) = &console_log_spec {
+        let control = SinkControl::new("console", spec);
+        writers.push(Box::new(FilteredWriter::new(spec.clone(), CountingWriter::new(control.counters.clone(), ConsoleWriter::new(sinks.stderr_level)))
+            .with_spec_override(control.spec_override.clone())));
+        sink_controls.push(control);
+    }
+    let file_writer = file_log_spec.as_ref().map(|_| RoutingFileWriter::in_directory(sinks.log_dir.as_deref(), &sinks.file_routes));
+    if let (Some(spec), Some(file_writer)) = (&file_log_spec, &file_writer) {
+        let control = SinkControl::new("file", spec);
+        writers.push(Box::new(FilteredWriter::new(spec.clone(), CountingWriter::new(control.counters.clone(), file_writer.clone()))
+            .with_spec_override(control.spec_override.clone())));
+        sink_controls.push(control);
+    }
+    let specs: Vec<&LogSpecification> = console_log_spec.iter().chain(file_log_spec.iter()).collect();
+    let configured = union_of_log_specs(&specs);
+
+    let writer = MultiWriter::new(writers);
+    let logger = Logger::with(configured.clone())
         .format(detailed_format)
-        .print_message();
-    if log_to_stdout {
-        logger = logger.duplicate_to_stdout(Duplicate::All)
+        .log_to_writer(Box::new(writer.clone()));
+    (logger, LocalSinkControls {
+        configured,
+        file_writer,
+        writer,
+        sinks: sink_controls,
+    })
+}
+
+/// Lets `control::handle()` change the spec of a started local logger.
+struct LocalLoggerControl {
+    handle: LoggerHandle,
+    sinks: LocalSinkControls,
+}
+
+impl Backend for LocalLoggerControl {
+    fn set_spec(&mut self, spec: Option<&LogSpecification>) {
+        for sink in &self.sinks.sinks {
+            sink.set_spec_override(spec.cloned());
+        }
+        self.handle.set_new_spec(spec.unwrap_or(&self.sinks.configured).clone());
+        // as set by flexi_logger
+        crate::pipeline::set_max_level(log::max_level());
+    }
+
+    fn configured_spec(&self) -> &LogSpecification {
+        &self.sinks.configured
+    }
+
+    /// each sink is raised from its own spec, flexi_logger gets the union of them
+    fn set_raised_spec(&mut self, notches: usize) -> String {
+        for sink in &self.sinks.sinks {
+            sink.set_spec_override((notches > 0).then(|| crate::control::raised(&sink.spec, notches)));
+        }
+        let specs: Vec<LogSpecification> = self.sinks.sinks.iter().map(SinkControl::effective_spec).collect();
+        self.handle.set_new_spec(union_of_log_specs(&specs.iter().collect::<Vec<_>>()));
+        crate::pipeline::set_max_level(log::max_level());
+        let specs: Vec<(&str, String)> = self.sinks.sinks.iter().zip(&specs).map(|(sink, spec)| (sink.name, spec.to_string())).collect();
+        describe_specs(&specs)
+    }
+
+    fn reopen_files(&mut self) -> Result<(), String> {
+        match &self.sinks.file_writer {
+            Some(file_writer) => file_writer.reopen_outputfile().map_err(|err| err.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    fn sinks(&self) -> Vec<SinkStats> {
+        self.sinks.sinks
+            .iter()
+            .map(|sink| sink.counters.stats(sink.name, &sink.effective_spec().to_string()))
+            .collect()
     }
-    if log_to_file {
-        logger = logger.log_to_file(FileSpec::default())
-            .rotate(
-                Criterion::Age(Age::new_with_splitting_at_every_new_day_by_offset_hour(8)),
-                Naming::Timestamps(time::UtcOffset::from_hms(8, 0, 0).unwrap()),          // - let the rotated files have a timestamp in their name
-                Cleanup::KeepLogFiles(1024),    // - keep at most 1024 log files
-            );
+
+    fn flush(&self) {
+        // the stages of `pipeline` first, eg: the summary held back by the deduplicator
+        log::logger().flush();
+        self.handle.flush();
+    }
+
+    fn shutdown(&mut self) {
+        self.flush();
+        self.handle.shutdown();
     }
+}
+
+/// note:
+/// 1. Current log will always output to File1: ${package_name}_rCurrent.log, eg: buff_rCurrent.log
+/// 2. Additionally, (an empty file) File2: ${package_name}_r<Datetime-the-program-started>.log is also created. eg: buff_r2011-11-14T11:51:24+08.log.
+///    At every new day (00:00), all logs in File1 will move to File2, then File1 is truncated and served as current logging file.
+///    Thus, File2 includes the last day's log.
+///    eg: buff_r2011-11-14T11:51:24+08.log has logs from 2011-11-14T11:51:24+08.log to 2011-11-14T59:59:59+08.log
+///
+pub fn default_logger(log_spec: &str, log_to_stdout: bool, log_to_file: bool) -> Logger {
+    local_logger(&LocalSinks::new(log_spec, log_to_stdout, log_to_file))
+}
 
-    logger
+pub fn start_default_logger(log_spec: &str, log_to_stdout: bool, log_to_file: bool) -> LoggerHandle {
+    start_local_logger(&LocalSinks::new(log_spec, log_to_stdout, log_to_file))
 }
 
-pub fn start_default_logger(log_spec: &str, log_to_stdout: bool, log_to_file: bool) -> flexi_logger::LoggerHandle {
-    default_logger(log_spec, log_to_stdout, log_to_file)
-        .start()
+/// records go through `pipeline` first, the returned handle is also registered in `control::handle()`
+pub fn start_local_logger(sinks: &LocalSinks) -> LoggerHandle {
+    let (logger, sink_controls) = build_local_logger(sinks);
+    let (boxed_logger, handle) = logger
+        .build()
+        .map_err(|err| {
+            println!("Could not build logger, err: {:?}", err);
+        })
+        .expect("start default logger error");
+    // flexi_logger drops what its spec is not enabled for before its writers, the ring buffer is written to them directly
+    let writer = sink_controls.writer.clone();
+    let replay = Box::new(move |record: &Record| {
+        writer.write(&mut DeferredNow::new(), record).ok();
+    });
+    crate::pipeline::set_boxed_logger_with_replay(boxed_logger, Some(replay))
         .map_err(|err| {
             println!("Could not start logger, err: {:?}", err);
         })
-        .expect("start default logger error")
+        .expect("start default logger error");
+    // as set by `build()`
+    crate::pipeline::set_max_level(log::max_level());
+    crate::control::handle().register(&sinks.describe_log_specs(), Box::new(LocalLoggerControl {
+        handle: handle.clone(),
+        sinks: sink_controls,
+    }));
+    handle
 }
 
 
@@ -116,6 +699,7 @@ mod test {
     #[test]
     fn test_flexi_logger() {
         use super::*;
+        let _lock = crate::test_helper::logger_lock();
         start_default_logger("debug", true, true);
 
         // debug!("logger: {:#?}", logger);
@@ -124,7 +708,110 @@ mod test {
             info!("info: {}", i);
             warn!("warn: {}", i);
             error!("error: {}", i);
+            info!(user_id = 42, room = "abc"; "joined: {}", i);
             std::thread::sleep(Duration::from_secs(2));
         }
     }
+
+    #[test]
+    fn test_json_format_time_at_template_offset() {
+        use super::*;
+        let mut line = vec![];
+        let record = Record::builder().level(Level::Info).target("portal").args(format_args!("started")).build();
+        json_format(&mut line, &mut DeferredNow::new(), &record).unwrap();
+        let line = String::from_utf8(line).unwrap();
+        let time = line.split(r#""time":""#).nth(1).and_then(|rest| rest.split('"').next()).unwrap();
+        assert!(time.ends_with(&format!(" {}", template_offset())), "{}", time);
+    }
+
+    #[test]
+    fn test_file_route_matches() {
+        use super::*;
+        let record = |level, target| Record::builder().level(level).target(target).build();
+        let audit = FileRoute::for_targets("audit", &["audit", "access"]);
+        assert!(audit.matches(&record(Level::Info, "audit")));
+        assert!(audit.matches(&record(Level::Debug, "access::http")));
+        assert!(!audit.matches(&record(Level::Error, "portal")));
+        assert!(!audit.matches(&record(Level::Info, "auditor")));
+
+        let errors = FileRoute::for_level("error", LevelFilter::Error);
+        assert!(errors.matches(&record(Level::Error, "portal")));
+        assert!(!errors.matches(&record(Level::Warn, "portal")));
+    }
+
+    #[test]
+    fn test_filtered_writer_spec_override() {
+        use super::*;
+        let spec_override = SpecOverride::default();
+        let writer = FilteredWriter::new(LogSpecification::parse("warn").unwrap(), MultiWriter::new(vec![]))
+            .with_spec_override(spec_override.clone());
+        let enabled = |level, msg: &str| writer.enabled(&Record::builder().level(level).target("portal").args(format_args!("{}", msg)).build());
+        assert!(!enabled(Level::Info, "started"));
+        *spec_override.write().unwrap() = Some(LogSpecification::parse("debug").unwrap());
+        assert!(enabled(Level::Debug, "started"));
+        assert_eq!(writer.max_log_level(), LevelFilter::Debug);
+
+        *spec_override.write().unwrap() = Some(LogSpecification::parse("debug/time.?out").unwrap());
+        assert!(enabled(Level::Warn, "read timeout"));
+        assert!(!enabled(Level::Warn, "started"));
+    }
+
+    #[test]
+    fn test_describe_log_specs() {
+        use super::*;
+        assert_eq!(LocalSinks::new("debug", true, true).describe_log_specs(), "debug");
+        let mut sinks = LocalSinks::new("debug", true, true);
+        sinks.console_log_spec = Some("warn".to_string());
+        assert_eq!(sinks.describe_log_specs(), "console: warn; file: debug");
+    }
+
+    #[test]
+    fn test_raised_spec_per_sink() {
+        use super::*;
+        // set_new_spec changes the global max level
+        let _lock = crate::test_helper::logger_lock();
+        let mut sinks = LocalSinks::new("debug,h2=off", true, true);
+        sinks.console_log_spec = Some("warn".to_string());
+        sinks.log_dir = Some(std::env::temp_dir().join("fblog_test_raised_spec_per_sink"));
+        let (logger, sink_controls) = build_local_logger(&sinks);
+        let (_logger, handle) = logger.build().unwrap();
+        let mut control = LocalLoggerControl { handle, sinks: sink_controls };
+        let specs = |control: &LocalLoggerControl| control.sinks().into_iter().map(|sink| sink.spec).collect::<Vec<_>>();
+
+        assert_eq!(control.set_raised_spec(1), "console: info; file: trace,h2=off");
+        assert_eq!(specs(&control), vec!["info", "trace,h2=off"]);
+        control.set_spec(Some(&LogSpecification::parse("error").unwrap()));
+        assert_eq!(specs(&control), vec!["error", "error"]);
+        control.set_spec(None);
+        assert_eq!(specs(&control), vec!["warn", "debug,h2=off"]);
+        control.shutdown();
+        crate::control::handle().reset_spec().ok();
+    }
+
+    #[test]
+    fn test_union_of_log_specs() {
+        use super::*;
+        let debug = LogSpecification::parse("debug").unwrap();
+        let warn_with_h2 = LogSpecification::parse("warn,h2=trace,hyper=error").unwrap();
+        let union = union_of_log_specs(&[&debug, &warn_with_h2]);
+        assert_eq!(max_level_of(&union), LevelFilter::Trace);
+        assert!(union.enabled(Level::Debug, "portal"));
+        assert!(!union.enabled(Level::Trace, "portal"));
+        assert!(union.enabled(Level::Trace, "h2::codec"));
+        assert!(union.enabled(Level::Debug, "hyper"));
+        // h2 is not a parent of h2x
+        let info_with_h2 = LogSpecification::parse("info,h2=trace").unwrap();
+        let warn_with_h2x = LogSpecification::parse("warn,h2x=error").unwrap();
+        assert!(!union_of_log_specs(&[&info_with_h2, &warn_with_h2x]).enabled(Level::Debug, "h2x"));
+
+        let warn = LogSpecification::parse("warn").unwrap();
+        assert!(!union_of_log_specs(&[&warn]).enabled(Level::Info, "portal"));
+        // the textfilter is kept only if every sink has it
+        let warn_timeout = LogSpecification::parse("warn/timeout").unwrap();
+        let debug_timeout = LogSpecification::parse("debug/timeout").unwrap();
+        let text_filter = |spec: LogSpecification| spec.text_filter().map(|filter| filter.as_str().to_string());
+        assert_eq!(text_filter(union_of_log_specs(&[&warn_timeout, &debug_timeout])).as_deref(), Some("timeout"));
+        assert_eq!(text_filter(union_of_log_specs(&[&warn_timeout, &debug])), None);
+        assert_eq!(max_level_of(&union_of_log_specs(&[])), LevelFilter::Off);
+    }
 }
\ No newline at end of file
@@ -1,11 +1,16 @@
+use std::io::Write;
 use std::net::SocketAddr;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 
+use flexi_logger::{LogSpecBuilder, LogSpecification};
 use log::*;
 use log::LevelFilter;
-use syslog::{BasicLogger, Facility, Formatter3164};
+use syslog::{Facility, Formatter3164, LoggerBackend};
 
-static G_UDP_LOGGER_STARTED_IN_TEST: AtomicBool = AtomicBool::new(false);
+use crate::config_for_flexi_logger::max_level_of;
+use crate::control::{Backend, SinkCounters, SinkStats};
+use crate::key_values::AsStructuredData;
+use crate::syslog_codes::{SeverityMap, SyslogFacility, SyslogSeverity};
 
 /// fallback/default is: LevelFilter::Info
 pub fn get_formal_log_level_from_str(s: &str) -> log::LevelFilter {
@@ -20,6 +25,145 @@ pub fn get_formal_log_level_from_str(s: &str) -> log::LevelFilter {
     }
 }
 
+/// A `log::Log` writing to a syslog connection.
+/// <br>
+/// Same as `syslog::BasicLogger`, except that a record with key-values is sent as an RFC 5424 message,
+/// with the key-values as its structured data, eg:
+/// <br>
+/// ```info!(user_id = 42, room = "abc"; "joined")``` -> ```<14>1 2026-10-19T08:03:29.123Z host_10.0.0.7 portal 42 - [fblog@32473 user_id="42" room="abc"] joined```
+/// <br>
+/// The other records are sent as RFC 3164 messages, as by `Formatter3164`.
+/// <br>
+/// Records are filtered by a LogSpecification, so module filters like "info,h2=warn" work as for the local logger,
+/// and sent with the severity of their target and level in a `SeverityMap`.
+pub struct SyslogLogger {
+    logger: Mutex<syslog::Logger<LoggerBackend, Formatter3164>>,
+    spec: Arc<RwLock<LogSpecification>>,
+    severities: SeverityMap,
+    counters: Arc<SinkCounters>,
+}
+
+impl SyslogLogger {
+    /// everything up to `log::max_level()` is sent
+    pub fn new(logger: syslog::Logger<LoggerBackend, Formatter3164>) -> Self {
+        Self::with_spec(logger, LogSpecBuilder::new().default(LevelFilter::Trace).build())
+    }
+
+    pub fn with_spec(logger: syslog::Logger<LoggerBackend, Formatter3164>, spec: LogSpecification) -> Self {
+        Self {
+            logger: Mutex::new(logger),
+            spec: Arc::new(RwLock::new(spec)),
+            severities: SeverityMap::default(),
+            counters: Arc::default(),
+        }
+    }
+
+    /// default: `SeverityMap::default()`
+    pub fn with_severity_map(mut self, severities: SeverityMap) -> Self {
+        self.severities = severities;
+        self
+    }
+
+    /// the message matches the textfilter of the spec (eg: "info/timeout"), if any
+    fn matches_text_filter(&self, record: &Record) -> bool {
+        let spec = self.spec.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        spec.text_filter().is_none_or(|filter| filter.is_match(&record.args().to_string()))
+    }
+}
+
+impl Log for SyslogLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+            && match self.spec.read() {
+                Ok(spec) => spec.enabled(metadata.level(), metadata.target()),
+                Err(poisoned) => poisoned.into_inner().enabled(metadata.level(), metadata.target()),
+            }
+    }
+
+    fn log(&self, record: &Record) {
+        // the records of the ring buffer are sent whatever the spec
+        if !crate::pipeline::is_replaying() && (!self.enabled(record.metadata()) || !self.matches_text_filter(record)) {
+            return;
+        }
+        let structured_data = AsStructuredData(record.key_values()).to_string();
+        let severity = self.severities.severity_for(record.target(), record.level());
+        let mut logger = match self.logger.lock() {
+            Ok(logger) => logger,
+            Err(poisoned) => poisoned.into_inner(),
+        };
+        if !structured_data.is_empty() {
+            let result = write_5424(&mut logger, severity, &structured_data, record.args());
+            self.counters.count(result.is_ok());
+            return;
+        }
+        let message = format!("{}", record.args());
+        let result = match severity {
+            SyslogSeverity::Emerg => logger.emerg(message),
+            SyslogSeverity::Alert => logger.alert(message),
+            SyslogSeverity::Crit => logger.crit(message),
+            SyslogSeverity::Err => logger.err(message),
+            SyslogSeverity::Warning => logger.warning(message),
+            SyslogSeverity::Notice => logger.notice(message),
+            SyslogSeverity::Info => logger.info(message),
+            SyslogSeverity::Debug => logger.debug(message),
+        };
+        self.counters.count(result.is_ok());
+    }
+
+    fn flush(&self) {
+        if let Ok(mut logger) = self.logger.lock() {
+            let _ = logger.backend.flush();
+        }
+    }
+}
+
+/// `<PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID - STRUCTURED-DATA MSG`, the header fields taken from the `Formatter3164`.
+/// <br>
+/// Not through `syslog::Formatter5424`: it writes the parameters of the structured data unescaped and in no particular order.
+fn write_5424(logger: &mut syslog::Logger<LoggerBackend, Formatter3164>, severity: SyslogSeverity, structured_data: &str,
+              message: &std::fmt::Arguments) -> std::io::Result<()> {
+    let formatter = &logger.formatter;
+    let nil_if_empty = |field: &str| if field.is_empty() { "-".to_string() } else { field.to_string() };
+    // one write per message: the backend sends a datagram per write
+    let line = format!("<{}>1 {} {} {} {} - {} {}",
+                       formatter.facility as u8 | severity.code(),
+                       chrono::Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                       nil_if_empty(formatter.hostname.as_deref().unwrap_or_default()),
+                       nil_if_empty(&formatter.process),
+                       formatter.pid,
+                       structured_data,
+                       message);
+    logger.backend.write_all(line.as_bytes())
+}
+
+/// Lets `control::handle()` change the spec of a started syslog logger.
+struct SyslogControl {
+    /// eg: "syslog(udp) 172.17.0.2:514"
+    name: String,
+    spec: Arc<RwLock<LogSpecification>>,
+    configured: LogSpecification,
+    counters: Arc<SinkCounters>,
+}
+
+impl Backend for SyslogControl {
+    fn set_spec(&mut self, spec: Option<&LogSpecification>) {
+        let spec = spec.unwrap_or(&self.configured).clone();
+        crate::pipeline::set_max_level(max_level_of(&spec));
+        if let Ok(mut current) = self.spec.write() {
+            *current = spec;
+        }
+    }
+
+    fn configured_spec(&self) -> &LogSpecification {
+        &self.configured
+    }
+
+    fn sinks(&self) -> Vec<SinkStats> {
+        let spec = self.spec.read().map(|spec| spec.to_string()).unwrap_or_default();
+        vec![self.counters.stats(&self.name, &spec)]
+    }
+}
+
 /// used in test.
 /// For main process_name, you can use:
 /// env: CARGO_PKG_NAME
@@ -33,12 +177,7 @@ pub fn start_udp_logger_in_test(
     remote_address: SocketAddr,
     level_filter: LevelFilter,
 ) {
-    if G_UDP_LOGGER_STARTED_IN_TEST.load(Ordering::SeqCst) {
-        debug!("udp logger already started.");
-        return;
-    }
     start_udp_logger(facility, process_name, local_address, remote_address, level_filter);
-    G_UDP_LOGGER_STARTED_IN_TEST.store(true, Ordering::SeqCst);
     println!("started syslog in test");
 }
 
@@ -55,6 +194,35 @@ pub fn start_udp_logger(
     remote_address: SocketAddr,
     level_filter: LevelFilter,
 ) {
+    let spec = LogSpecBuilder::new().default(level_filter).build();
+    start_udp_logger_with_spec(facility, process_name, local_address, remote_address, spec);
+}
+
+/// same as `start_udp_logger`, with module filters, eg: LogSpecification::parse("info,h2=warn").
+/// <br>
+/// The spec can be changed at runtime by `control::handle()`.
+pub fn start_udp_logger_with_spec(
+    facility: Facility,
+    process_name: &str,
+    local_address: SocketAddr,
+    remote_address: SocketAddr,
+    spec: LogSpecification,
+) {
+    start_udp_logger_with_severity_map(facility, process_name, local_address, remote_address, spec, SeverityMap::default());
+}
+
+/// same as `start_udp_logger_with_spec`, with the syslog severity of each level and target,
+/// eg: "error=crit,audit/*=notice".parse::<SeverityMap>()
+pub fn start_udp_logger_with_severity_map(
+    facility: Facility,
+    process_name: &str,
+    local_address: SocketAddr,
+    remote_address: SocketAddr,
+    spec: LogSpecification,
+    severities: SeverityMap,
+) {
+    let level_filter = max_level_of(&spec);
+    let configured = spec.to_string();
     let local_host_name = crate::hostname();
     let local_ip = crate::get_proper_ip().replace('/', "N");
     let hostname_in_log = local_host_name + "_" + &local_ip;
@@ -70,22 +238,30 @@ pub fn start_udp_logger(
     // let logger = syslog::unix(formatter).expect("could not connect to syslog");
     let logger = syslog::udp(formatter, local_address, remote_address)
         .expect("could not connect to syslog");
-    println!("Starting syslog(udp) with facility: {:?}.  Checkout rsyslogd config(/etc/rsyslog.conf), and find these 2 similar lines:\n
-local1.*			-/data/log/collected_by_rsyslog/open_platform.log
-local2.*			-/data/log/collected_by_rsyslog/bot_platform.log
+    println!("Starting syslog(udp) with facility: {facility}.  Checkout rsyslogd config(/etc/rsyslog.conf), and find a similar line:\n
+{facility}.*			-/data/log/collected_by_rsyslog/{process_name}.log
 
-# these are the file locations in udp server.
+# this is the file location in udp server.
 
 local_address: {:?},
-remote_address: {:?}
-", facility, local_address, remote_address);
-    log::set_boxed_logger(Box::new(BasicLogger::new(logger)))
-        .map(|()| log::set_max_level(level_filter))
+remote_address: {:?},
+severities: {}
+", local_address, remote_address, severities, facility = SyslogFacility(facility), process_name = process_name);
+    let syslog_logger = SyslogLogger::with_spec(logger, spec.clone()).with_severity_map(severities);
+    let control = SyslogControl {
+        name: format!("syslog(udp) {}", remote_address),
+        spec: syslog_logger.spec.clone(),
+        configured: spec,
+        counters: syslog_logger.counters.clone(),
+    };
+    crate::pipeline::set_boxed_logger(Box::new(syslog_logger))
+        .map(|()| crate::pipeline::set_max_level(level_filter))
         .map_err(|err| {
             error!("could not init syslog logger, err: {:#?}", err);
             err
         })
         .expect("could not init syslog logger");
+    crate::control::handle().register(&configured, Box::new(control));
 
     println!(r#"Started syslog(udp) with
     facility: {:?},
@@ -94,14 +270,14 @@ remote_address: {:?}
     pid: {},
     local_address: {},
     remote_address: {},
-    level_filter: {:?}"#,
+    log_spec: {}"#,
              facility,
              hostname_in_log,
              process_name,
              pid,
              local_address,
              remote_address,
-             level_filter
+             configured
     );
     debug!("debug");
     info!("info");
@@ -112,22 +288,23 @@ remote_address: {:?}
 mod test {
     use std::str::FromStr;
 
+    use std::time::Duration;
+
     use log::LevelFilter;
 
     // use get_formal_log_level_from_str;
     use super::*;
+    use crate::test_helper::SyslogReceiver;
 
     #[test]
     fn test_syslog_udp_sending() {
-        // start_default_logger("debug", true);
+        let _lock = crate::test_helper::logger_lock();
+        let receiver = SyslogReceiver::udp().unwrap();
         start_udp_logger_in_test(
             Facility::LOG_USER,
             "logger",
-            // SocketAddr::from_str("127.0.0.1:15541").unwrap(),
-            // SocketAddr::from_str("172.17.0.1:15541").unwrap(),
             SocketAddr::from_str("0.0.0.0:0").unwrap(),
-            SocketAddr::from_str("172.17.0.2:514").unwrap(), // docker: 172.17.0.2
-            // SocketAddr::from_str("127.0.0.1:514").unwrap(),
+            receiver.local_addr().unwrap(),
             LevelFilter::Debug);
 
         trace!("trace");
@@ -136,7 +313,71 @@ mod test {
         warn!("warn");
         error!("error");
         info!("multiline:\r\nline1\r\nline2\nline3\n");
-        // info!("local_host_name: {}, local_ip: {}", local_host_name, local_ip);
+        info!(user_id = 42, room = "abc"; "joined");
+
+        // records of other tests may be received too
+        let received = receiver.wait_until(Duration::from_secs(3), |message| message.message.ends_with("joined"));
+        let has = |severity: &str, message: &str| received.iter().any(|received| received.severity_name() == severity && received.message == message);
+        assert!(!received.iter().any(|received| received.message == "trace"));
+        assert!(has("debug", "debug"));
+        assert!(has("info", "info"));
+        assert!(has("warning", "warn"));
+        assert!(has("err", "error"));
+        assert!(received.iter().any(|received| received.message.starts_with("multiline:")));
+        let joined = received.last().unwrap();
+        assert_eq!(joined.message, "joined");
+        assert_eq!(joined.structured_data.as_deref(), Some(r#"[fblog@32473 user_id="42" room="abc"]"#));
+        assert!(joined.timestamp.as_deref().is_some_and(|timestamp| timestamp.ends_with('Z')), "{:?}", joined);
+        assert!(matches!(joined.facility, Facility::LOG_USER));
+        assert_eq!((joined.process.as_deref(), joined.pid), (Some("logger"), Some(std::process::id())));
+    }
+
+    #[test]
+    fn test_syslog_severity_map() {
+        let _lock = crate::test_helper::logger_lock();
+        let receiver = SyslogReceiver::udp().unwrap();
+        start_udp_logger_with_severity_map(
+            Facility::LOG_LOCAL1,
+            "portal",
+            SocketAddr::from_str("0.0.0.0:0").unwrap(),
+            receiver.local_addr().unwrap(),
+            LogSpecification::parse("info").unwrap(),
+            "error=crit, audit/*=notice, audit/error=alert".parse().unwrap());
+
+        error!("db down");
+        warn!("slow");
+        info!(target: "audit", "user 42 logged in");
+        error!(target: "audit", "user 42 locked out");
+
+        let received = receiver.wait_until(Duration::from_secs(3), |message| message.message == "user 42 locked out");
+        let severity_of = |text: &str| received.iter().find(|message| message.message == text).map(|message| message.severity);
+        assert_eq!(severity_of("db down"), Some(SyslogSeverity::Crit));
+        assert_eq!(severity_of("slow"), Some(SyslogSeverity::Warning));
+        assert_eq!(severity_of("user 42 logged in"), Some(SyslogSeverity::Notice));
+        assert_eq!(severity_of("user 42 locked out"), Some(SyslogSeverity::Alert));
+        assert!(matches!(received.last().unwrap().facility, Facility::LOG_LOCAL1));
+    }
+
+    #[test]
+    fn test_syslog_ring_buffer() {
+        let _lock = crate::test_helper::logger_lock();
+        let receiver = SyslogReceiver::udp().unwrap();
+        start_udp_logger_with_spec(
+            Facility::LOG_USER,
+            "portal",
+            SocketAddr::from_str("0.0.0.0:0").unwrap(),
+            receiver.local_addr().unwrap(),
+            LogSpecification::parse("info").unwrap());
+        crate::pipeline::set_ring_buffer(Some(crate::RingBuffer::new(10)));
+
+        debug!("connecting to db");
+        error!("db unreachable");
+        crate::pipeline::set_ring_buffer(None);
+
+        let received = receiver.wait_until(Duration::from_secs(3), |message| message.message == "db unreachable");
+        let buffered = received.iter().find(|message| message.message.ends_with("] connecting to db"));
+        // sent at its own level although the spec is info
+        assert_eq!(buffered.map(|message| message.severity), Some(SyslogSeverity::Debug), "{:?}", received);
     }
 
     #[test]
@@ -0,0 +1,266 @@
+use std::cell::RefCell;
+use std::fmt;
+use std::marker::PhantomData;
+
+use log::{Level, Record};
+
+use crate::key_values::OwnedPairs;
+
+thread_local! {
+    static CONTEXT_STACK: RefCell<Vec<(String, String)>> = const { RefCell::new(vec![]) };
+}
+
+/// Fields attached to every record of the `ctx_*!` macros while the context is entered, eg:
+/// ```ignore
+/// let _ctx = LogContext::new().with("request_id", &request_id).with("user_id", user_id).enter();
+/// ctx_info!("joined");  // -> ... joined request_id=r-81 user_id=42
+/// ```
+/// Contexts nest, a field of the inner context replaces the one of the outer context with the same key.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LogContext {
+    fields: Vec<(String, String)>,
+}
+
+impl LogContext {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, key: &str, value: impl fmt::Display) -> Self {
+        self.fields.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn fields(&self) -> &[(String, String)] {
+        &self.fields
+    }
+
+    /// push the fields onto the context of the current thread, until the guard is dropped
+    pub fn enter(&self) -> ContextGuard {
+        let len = CONTEXT_STACK.with(|stack| {
+            let mut stack = stack.borrow_mut();
+            let len = stack.len();
+            stack.extend(self.fields.iter().cloned());
+            len
+        });
+        ContextGuard { len, _not_send: PhantomData }
+    }
+}
+
+/// Returned by `LogContext::enter`, pops the context when dropped.
+/// <br>
+/// It can't be sent to another thread, as the context belongs to the thread that entered it.
+#[must_use = "the context is popped when the guard is dropped"]
+pub struct ContextGuard {
+    len: usize,
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for ContextGuard {
+    fn drop(&mut self) {
+        CONTEXT_STACK.with(|stack| stack.borrow_mut().truncate(self.len));
+    }
+}
+
+/// the fields of the contexts entered on the current thread, outer ones first
+pub fn current() -> Vec<(String, String)> {
+    with_fields(|fields| fields.to_vec())
+}
+
+/// the fields of the task context (if any) come first, then the ones of the thread
+fn with_fields<R>(f: impl FnOnce(&[(String, String)]) -> R) -> R {
+    CONTEXT_STACK.with(|stack| {
+        let stack = stack.borrow();
+        match task_fields() {
+            Some(mut fields) => {
+                fields.extend(stack.iter().cloned());
+                f(&fields)
+            }
+            None => f(&stack),
+        }
+    })
+}
+
+#[cfg(feature = "tokio")]
+tokio::task_local! {
+    static TASK_CONTEXT: Vec<(String, String)>;
+}
+
+#[cfg(feature = "tokio")]
+fn task_fields() -> Option<Vec<(String, String)>> {
+    TASK_CONTEXT.try_with(|fields| fields.clone()).ok()
+}
+
+#[cfg(not(feature = "tokio"))]
+fn task_fields() -> Option<Vec<(String, String)>> {
+    None
+}
+
+/// Run `future` with the fields of `ctx` attached to the records of the `ctx_*!` macros inside it,
+/// on whichever thread the future is polled, eg:
+/// ```ignore
+/// tokio::spawn(fblog::with_context(LogContext::new().with("request_id", id), handle(request)));
+/// ```
+/// In async code use this instead of `LogContext::enter`: a thread context held across an `.await`
+/// would show up in the records of other tasks polled on the same thread.
+/// <br>
+/// Contexts nest, the fields of the surrounding task context are kept.
+#[cfg(feature = "tokio")]
+pub fn with_context<F: std::future::Future>(ctx: LogContext, future: F) -> impl std::future::Future<Output = F::Output> {
+    let mut fields = task_fields().unwrap_or_default();
+    fields.extend(ctx.fields);
+    TASK_CONTEXT.scope(fields, future)
+}
+
+/// used by the `ctx_*!` macros, the key-values given to the macro come after the fields of the context
+#[doc(hidden)]
+pub fn __log(level: Level, target: &str, module_path: &'static str, file: &'static str, line: u32,
+             kvs: &[(&str, &dyn fmt::Display)], args: fmt::Arguments) {
+    with_fields(|fields| {
+        let mut fields = fields.to_vec();
+        fields.extend(kvs.iter().map(|(key, value)| (key.to_string(), value.to_string())));
+        let kvs = OwnedPairs(&fields);
+        log::logger().log(&Record::builder()
+            .level(level)
+            .target(target)
+            .module_path_static(Some(module_path))
+            .file_static(Some(file))
+            .line(Some(line))
+            .key_values(&kvs)
+            .args(args)
+            .build());
+    });
+}
+
+/// Same as `log!`, with the fields of the entered `LogContext`s attached as key-values.
+/// <br>
+/// Key-values can be given as with `log!`, they replace the fields of the context with the same key, eg:
+/// ```ignore
+/// ctx_info!(user_id = 7, room = "abc"; "joined");  // -> ... joined request_id=r-81 user_id=7 room=abc
+/// ```
+#[macro_export]
+macro_rules! ctx_log {
+    (target: $target:expr, $lvl:expr, $($key:ident = $value:expr),+; $($arg:tt)+) => ({
+        let lvl = $lvl;
+        if lvl <= $crate::log::STATIC_MAX_LEVEL && lvl <= $crate::log::max_level() {
+            $crate::context::__log(lvl, $target, module_path!(), file!(), line!(),
+                                   &[$((stringify!($key), &$value as &dyn ::std::fmt::Display)),+], format_args!($($arg)+));
+        }
+    });
+    (target: $target:expr, $lvl:expr, $($arg:tt)+) => ({
+        let lvl = $lvl;
+        if lvl <= $crate::log::STATIC_MAX_LEVEL && lvl <= $crate::log::max_level() {
+            $crate::context::__log(lvl, $target, module_path!(), file!(), line!(), &[], format_args!($($arg)+));
+        }
+    });
+    ($lvl:expr, $($arg:tt)+) => ($crate::ctx_log!(target: module_path!(), $lvl, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! ctx_trace {
+    (target: $target:expr, $($arg:tt)+) => ($crate::ctx_log!(target: $target, $crate::log::Level::Trace, $($arg)+));
+    ($($arg:tt)+) => ($crate::ctx_log!($crate::log::Level::Trace, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! ctx_debug {
+    (target: $target:expr, $($arg:tt)+) => ($crate::ctx_log!(target: $target, $crate::log::Level::Debug, $($arg)+));
+    ($($arg:tt)+) => ($crate::ctx_log!($crate::log::Level::Debug, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! ctx_info {
+    (target: $target:expr, $($arg:tt)+) => ($crate::ctx_log!(target: $target, $crate::log::Level::Info, $($arg)+));
+    ($($arg:tt)+) => ($crate::ctx_log!($crate::log::Level::Info, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! ctx_warn {
+    (target: $target:expr, $($arg:tt)+) => ($crate::ctx_log!(target: $target, $crate::log::Level::Warn, $($arg)+));
+    ($($arg:tt)+) => ($crate::ctx_log!($crate::log::Level::Warn, $($arg)+));
+}
+
+#[macro_export]
+macro_rules! ctx_error {
+    (target: $target:expr, $($arg:tt)+) => ($crate::ctx_log!(target: $target, $crate::log::Level::Error, $($arg)+));
+    ($($arg:tt)+) => ($crate::ctx_log!($crate::log::Level::Error, $($arg)+));
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key_values::{collect, AsText};
+
+    fn fields() -> String {
+        with_fields(|fields| AsText(&OwnedPairs(fields)).to_string())
+    }
+
+    #[test]
+    fn test_context_stack() {
+        assert_eq!(fields(), "");
+        let outer = LogContext::new().with("request_id", "r-81").with("user_id", 42).enter();
+        assert_eq!(fields(), " request_id=r-81 user_id=42");
+        {
+            let _inner = LogContext::new().with("user_id", 7).with("session", "s 1").enter();
+            assert_eq!(fields(), " request_id=r-81 user_id=7 session=\"s 1\"");
+        }
+        assert_eq!(with_fields(|fields| collect(&OwnedPairs(fields))), vec![
+            ("request_id".to_string(), "r-81".to_string()),
+            ("user_id".to_string(), "42".to_string()),
+        ]);
+        drop(outer);
+        assert_eq!(current(), vec![]);
+
+        // other threads have their own context
+        let _ctx = LogContext::new().with("request_id", "r-82").enter();
+        assert_eq!(std::thread::spawn(current).join().unwrap(), vec![]);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn test_with_context() {
+        let runtime = tokio::runtime::Builder::new_multi_thread().worker_threads(4).build().unwrap();
+        let tasks: Vec<_> = (0..32)
+            .map(|i| runtime.spawn(with_context(LogContext::new().with("request_id", i), async move {
+                let mut seen = vec![];
+                for _ in 0..10 {
+                    tokio::task::yield_now().await;
+                    seen.push(current());
+                }
+                let nested = with_context(LogContext::new().with("user_id", 42), async { current() }).await;
+                (i, seen, nested)
+            })))
+            .collect();
+        for task in tasks {
+            let (i, seen, nested) = runtime.block_on(task).unwrap();
+            let request_id = ("request_id".to_string(), i.to_string());
+            assert!(seen.iter().all(|fields| fields == &vec![request_id.clone()]));
+            assert_eq!(nested, vec![request_id, ("user_id".to_string(), "42".to_string())]);
+        }
+        assert_eq!(current(), vec![]);
+    }
+
+    #[test]
+    fn test_ctx_macros() {
+        let capture = crate::test_helper::capture();
+        let _ctx = LogContext::new().with("request_id", "r-83").with("user_id", 42).enter();
+        ctx_trace!("trace");
+        ctx_debug!("{}", 10);
+        ctx_info!(target: "portal", user_id = 7, room = "abc"; "joined {}", "room");
+        ctx_warn!(elapsed_ms = 1200; "slow");
+        ctx_error!(target: "portal", "error");
+
+        let records: Vec<_> = capture.records().into_iter()
+            .map(|record| (record.level, record.target, record.message, record.key_values))
+            .collect();
+        let kvs = |pairs: &[(&str, &str)]| pairs.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect::<Vec<_>>();
+        let module = module_path!().to_string();
+        assert_eq!(records, vec![
+            (Level::Trace, module.clone(), "trace".to_string(), kvs(&[("request_id", "r-83"), ("user_id", "42")])),
+            (Level::Debug, module.clone(), "10".to_string(), kvs(&[("request_id", "r-83"), ("user_id", "42")])),
+            (Level::Info, "portal".to_string(), "joined room".to_string(), kvs(&[("request_id", "r-83"), ("user_id", "7"), ("room", "abc")])),
+            (Level::Warn, module, "slow".to_string(), kvs(&[("request_id", "r-83"), ("user_id", "42"), ("elapsed_ms", "1200")])),
+            (Level::Error, "portal".to_string(), "error".to_string(), kvs(&[("request_id", "r-83"), ("user_id", "42")])),
+        ]);
+    }
+}
@@ -0,0 +1,608 @@
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use flexi_logger::{LogSpecBuilder, LogSpecification, ModuleFilter};
+use log::LevelFilter;
+use once_cell::sync::Lazy;
+
+const DEFAULT_SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+static G_CONTROL_HANDLE: Lazy<ControlHandle> = Lazy::new(ControlHandle::new);
+
+/// The process-global handle of the started logger, see `ControlHandle`.
+pub fn handle() -> &'static ControlHandle {
+    &G_CONTROL_HANDLE
+}
+
+/// What a started logger has to support to be controlled at runtime.
+pub(crate) trait Backend: Send {
+    /// Some: use `spec` for every sink.
+    /// None: go back to the spec(s) the logger was started with.
+    fn set_spec(&mut self, spec: Option<&LogSpecification>);
+
+    /// the spec the logger was started with, the union of them if the sinks have their own
+    fn configured_spec(&self) -> &LogSpecification;
+
+    /// Go back to the spec(s) the logger was started with, each raised `notches` times by `more_verbose`,
+    /// returns the spec(s) now used.
+    /// <br>
+    /// Backends whose sinks have their own spec raise each of them, eg: "console: info; file: trace".
+    fn set_raised_spec(&mut self, notches: usize) -> String {
+        let spec = raised(self.configured_spec(), notches);
+        self.set_spec((notches > 0).then_some(&spec));
+        spec.to_string()
+    }
+
+    /// reopen the log files, eg: after they were moved by logrotate
+    fn reopen_files(&mut self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// the sinks records are written to, with their stats
+    fn sinks(&self) -> Vec<SinkStats>;
+
+    fn flush(&self) {
+        log::logger().flush();
+    }
+
+    /// flush and shut down all sinks, nothing is written afterwards
+    fn shutdown(&mut self) {
+        self.flush();
+    }
+}
+
+/// Records written to a sink, shared between the sink and its `Backend`.
+#[derive(Debug, Default)]
+pub struct SinkCounters {
+    written: AtomicU64,
+    failed: AtomicU64,
+}
+
+impl SinkCounters {
+    /// count a record, `ok`: if it was written without error
+    pub fn count(&self, ok: bool) {
+        let counter = if ok { &self.written } else { &self.failed };
+        counter.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn stats(&self, name: &str, spec: &str) -> SinkStats {
+        SinkStats {
+            name: name.to_string(),
+            spec: spec.to_string(),
+            written: self.written.load(Ordering::Relaxed),
+            failed: self.failed.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// A snapshot of a sink of the started logger, see `ControlHandle::sinks`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SinkStats {
+    /// eg: "console", "file", "syslog(udp) 172.17.0.2:514"
+    pub name: String,
+    /// the effective spec of the sink
+    pub spec: String,
+    /// records written since the logger was started
+    pub written: u64,
+    /// records that could not be written
+    pub failed: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlError {
+    /// no logger was started by fblog yet
+    NotStarted,
+    /// the spec could not be parsed, with the reason
+    InvalidSpec(String),
+    /// the log files could not be reopened, with the reason
+    Reopen(String),
+    /// the sinks were not shut down in time
+    Timeout,
+}
+
+impl fmt::Display for ControlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlError::NotStarted => write!(f, "no logger started"),
+            ControlError::InvalidSpec(reason) => write!(f, "invalid log spec: {}", reason),
+            ControlError::Reopen(reason) => write!(f, "could not reopen log files: {}", reason),
+            ControlError::Timeout => write!(f, "timeout"),
+        }
+    }
+}
+
+impl std::error::Error for ControlError {}
+
+struct SpecEntry {
+    spec: String,
+    parsed: LogSpecification,
+    /// set by `push_temp_spec_for`
+    expires_at: Option<Instant>,
+}
+
+impl SpecEntry {
+    fn parse(spec: &str) -> Result<SpecEntry, ControlError> {
+        // LogSpecification parses "" as all off, which is never what an empty request means
+        if spec.trim().is_empty() {
+            return Err(ControlError::InvalidSpec("empty log spec, use \"off\" to turn logging off".to_string()));
+        }
+        let parsed = LogSpecification::parse(spec).map_err(|err| ControlError::InvalidSpec(err.to_string()))?;
+        Ok(SpecEntry {
+            spec: spec.to_string(),
+            parsed,
+            expires_at: None,
+        })
+    }
+}
+
+#[derive(Default)]
+struct State {
+    backend: Option<Box<dyn Backend>>,
+    /// description of the spec(s) the logger was started with
+    configured: String,
+    /// set by `set_spec`, None: use the configured spec(s)
+    base: Option<SpecEntry>,
+    /// pushed by `push_temp_spec`, the last one is effective
+    temp: Vec<SpecEntry>,
+    /// how many times `increase_verbosity` raised the configured spec(s), and the raised spec(s)
+    raised: Option<(usize, String)>,
+    /// incremented by `register`, tells the started loggers apart
+    generation: u64,
+}
+
+impl State {
+    fn effective(&self) -> Option<&SpecEntry> {
+        self.temp.last().or(self.base.as_ref())
+    }
+
+    fn apply(&mut self) -> Result<(), ControlError> {
+        let spec = self.temp.last().or(self.base.as_ref()).map(|entry| &entry.parsed);
+        match (&mut self.backend, spec, &self.raised) {
+            (Some(backend), Some(spec), _) => backend.set_spec(Some(spec)),
+            (Some(backend), None, Some((notches, _))) => {
+                backend.set_raised_spec(*notches);
+            }
+            (Some(backend), None, None) => backend.set_spec(None),
+            (None, ..) => return Err(ControlError::NotStarted),
+        }
+        Ok(())
+    }
+}
+
+/// Changes the log spec of the started logger at runtime, in the same way for every backend
+/// (console/file logger and syslog).
+/// <br>
+/// A spec set here applies to all sinks, overriding the specs they were started with
+/// (eg: LocalLog::with_console_log_spec), until `reset_spec` is called.
+/// <br>
+/// eg: debug for 5 minutes, then back to what it was:
+/// fblog::handle().push_temp_spec_for("debug", Duration::from_secs(300))
+pub struct ControlHandle {
+    state: Mutex<State>,
+}
+
+impl ControlHandle {
+    pub(crate) fn new() -> Self {
+        Self { state: Mutex::new(State::default()) }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// the effective spec, None if no logger was started yet
+    pub fn current_spec(&self) -> Option<String> {
+        let state = self.state();
+        state.backend.as_ref()?;
+        let raised = state.raised.as_ref().map(|(_, spec)| spec);
+        Some(state.effective().map(|entry| &entry.spec).or(raised).unwrap_or(&state.configured).clone())
+    }
+
+    /// replace the spec, eg: "debug", "info,h2=warn"
+    pub fn set_spec(&self, spec: &str) -> Result<(), ControlError> {
+        let mut state = self.state();
+        let entry = SpecEntry::parse(spec)?;
+        state.base = Some(entry);
+        state.apply()
+    }
+
+    /// drop all specs set at runtime and go back to the spec(s) the logger was started with
+    pub fn reset_spec(&self) -> Result<(), ControlError> {
+        let mut state = self.state();
+        state.base = None;
+        state.temp.clear();
+        state.raised = None;
+        state.apply()
+    }
+
+    /// use `spec` until `pop_temp_spec` is called
+    pub fn push_temp_spec(&self, spec: &str) -> Result<(), ControlError> {
+        self.push_temp_spec_until(spec, None)
+    }
+
+    /// use `spec` for `duration`, then revert, eg: push_temp_spec_for("debug", Duration::from_secs(300))
+    pub fn push_temp_spec_for(&'static self, spec: &str, duration: Duration) -> Result<(), ControlError> {
+        self.push_temp_spec_until(spec, Some(Instant::now() + duration))?;
+        std::thread::spawn(move || {
+            std::thread::sleep(duration);
+            self.expire_temp_specs(Instant::now());
+        });
+        Ok(())
+    }
+
+    /// revert the last pushed spec, no-op if there is none
+    pub fn pop_temp_spec(&self) {
+        let mut state = self.state();
+        if state.temp.pop().is_some() {
+            state.apply().ok();
+        }
+    }
+
+    fn push_temp_spec_until(&self, spec: &str, expires_at: Option<Instant>) -> Result<(), ControlError> {
+        let mut state = self.state();
+        let entry = SpecEntry::parse(spec)?;
+        state.temp.push(SpecEntry { expires_at, ..entry });
+        state.apply()
+    }
+
+    /// revert the specs pushed by `push_temp_spec_for` that expired at `now`
+    fn expire_temp_specs(&self, now: Instant) {
+        let mut state = self.state();
+        let len = state.temp.len();
+        state.temp.retain(|entry| entry.expires_at.is_none_or(|expires_at| expires_at > now));
+        if state.temp.len() != len {
+            state.apply().ok();
+        }
+    }
+
+    /// Raise every level of the effective spec one notch (eg: "info,h2=warn" -> "debug,h2=info"), `off` stays off.
+    /// <br>
+    /// Without a spec set at runtime, the spec of each sink is raised from its own level,
+    /// eg: "console: warn; file: debug" -> "console: info; file: trace".
+    /// <br>
+    /// The raised spec is kept until `reset_spec`, or until the spec pushed by `push_temp_spec_for` it raised expires.
+    /// Returns the new spec.
+    pub fn increase_verbosity(&self) -> Result<String, ControlError> {
+        let mut state = self.state();
+        let state = &mut *state;
+        let backend = state.backend.as_mut().ok_or(ControlError::NotStarted)?;
+        // pushed on top, expiring with the spec it raised, so a spec pushed for some minutes still reverts in time
+        if let Some(entry) = state.temp.last().or(state.base.as_ref()) {
+            let spec = more_verbose(&entry.parsed).to_string();
+            let expires_at = entry.expires_at;
+            state.temp.push(SpecEntry { expires_at, ..SpecEntry::parse(&spec)? });
+            return state.apply().map(|()| spec);
+        }
+        let notches = state.raised.as_ref().map_or(0, |(notches, _)| *notches) + 1;
+        let spec = backend.set_raised_spec(notches);
+        state.raised = Some((notches, spec.clone()));
+        Ok(spec)
+    }
+
+    /// reopen the log files of the started logger, eg: after logrotate moved them away
+    pub fn reopen_files(&self) -> Result<(), ControlError> {
+        match &mut self.state().backend {
+            Some(backend) => backend.reopen_files().map_err(ControlError::Reopen),
+            None => Err(ControlError::NotStarted),
+        }
+    }
+
+    /// the sinks of the started logger, with their stats
+    pub fn sinks(&self) -> Result<Vec<SinkStats>, ControlError> {
+        match &self.state().backend {
+            Some(backend) => Ok(backend.sinks()),
+            None => Err(ControlError::NotStarted),
+        }
+    }
+
+    /// flush all sinks of the started logger
+    pub fn flush(&self) -> Result<(), ControlError> {
+        match &self.state().backend {
+            Some(backend) => {
+                backend.flush();
+                Ok(())
+            }
+            None => Err(ControlError::NotStarted),
+        }
+    }
+
+    /// log the records kept by the ring buffer (see `pipeline::set_ring_buffer`) at their own level, returns how many were logged
+    pub fn dump_ring_buffer(&self) -> Result<usize, ControlError> {
+        match &self.state().backend {
+            Some(_) => Ok(crate::pipeline::dump_ring_buffer()),
+            None => Err(ControlError::NotStarted),
+        }
+    }
+
+    /// Flush and shut down all sinks, waiting at most `timeout`.
+    /// <br>
+    /// Afterwards the handle is detached from the logger, as if no logger was started.
+    pub fn shutdown(&self, timeout: Duration) -> Result<(), ControlError> {
+        let backend = self.state().backend.take().ok_or(ControlError::NotStarted)?;
+        shutdown_backend(backend, timeout)
+    }
+
+    /// Same as `shutdown`, if the started logger is still the one of `generation` (see `generation()`),
+    /// otherwise it was replaced by another start function and is kept.
+    pub fn shutdown_generation(&self, generation: u64, timeout: Duration) -> Result<(), ControlError> {
+        let backend = {
+            let mut state = self.state();
+            if state.generation != generation {
+                return Err(ControlError::NotStarted);
+            }
+            state.backend.take().ok_or(ControlError::NotStarted)?
+        };
+        shutdown_backend(backend, timeout)
+    }
+
+    /// identifies the started logger, changes each time a start function is called, None: not started
+    pub fn generation(&self) -> Option<u64> {
+        let state = self.state();
+        state.backend.as_ref().map(|_| state.generation)
+    }
+
+    /// called by the start functions, shuts down the previous backend (if any), returns the generation of `backend`
+    pub(crate) fn register(&self, configured: &str, backend: Box<dyn Backend>) -> u64 {
+        let (previous, generation) = {
+            let mut state = self.state();
+            let generation = state.generation + 1;
+            let previous = std::mem::replace(&mut *state, State {
+                backend: Some(backend),
+                configured: configured.to_string(),
+                generation,
+                ..State::default()
+            });
+            (previous.backend, generation)
+        };
+        // outside the lock, the previous backend may flush through the handle
+        if let Some(previous) = previous {
+            if let Err(err) = shutdown_backend(previous, DEFAULT_SHUTDOWN_TIMEOUT) {
+                eprintln!("could not shut down the previous logger: {}", err);
+            }
+        }
+        generation
+    }
+}
+
+fn shutdown_backend(mut backend: Box<dyn Backend>, timeout: Duration) -> Result<(), ControlError> {
+    let (done_sender, done) = std::sync::mpsc::channel();
+    // in another thread, so that a sink blocked by eg: a full disk can't block the caller
+    std::thread::spawn(move || {
+        backend.shutdown();
+        done_sender.send(()).ok();
+    });
+    done.recv_timeout(timeout).map_err(|_| ControlError::Timeout)
+}
+
+/// Flush and shut down all sinks of the started logger, waiting at most `timeout`, eg: in a signal handler:
+/// fblog::shutdown(Duration::from_secs(3))
+pub fn shutdown(timeout: Duration) -> Result<(), ControlError> {
+    handle().shutdown(timeout)
+}
+
+/// Returned by the start functions: flushes and shuts down all sinks when dropped, eg: at the end of `main`.
+/// <br>
+/// Keep it alive as long as the logger is used: `let _guard = fblog::start_logger_automatically(...);`
+/// <br>
+/// It belongs to the logger started with it: once another start function replaced that logger, dropping it does nothing.
+#[must_use = "dropping the guard shuts down the logger, bind it with `let _guard = ...`"]
+pub struct ShutdownGuard {
+    timeout: Duration,
+    generation: Option<u64>,
+}
+
+impl ShutdownGuard {
+    /// for the logger just registered by a start function
+    pub(crate) fn new() -> Self {
+        Self { timeout: DEFAULT_SHUTDOWN_TIMEOUT, generation: handle().generation() }
+    }
+
+    /// how long drop waits for the sinks to shut down, default: 5 seconds
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+}
+
+impl Drop for ShutdownGuard {
+    fn drop(&mut self) {
+        let Some(generation) = self.generation else {
+            return;
+        };
+        match handle().shutdown_generation(generation, self.timeout) {
+            // already shut down by `fblog::shutdown`, or replaced by another start function
+            Ok(()) | Err(ControlError::NotStarted) => {}
+            Err(err) => eprintln!("could not shut down logger: {}", err),
+        }
+    }
+}
+
+/// `spec` raised `notches` times by `more_verbose`
+pub(crate) fn raised(spec: &LogSpecification, notches: usize) -> LogSpecification {
+    (0..notches).fold(spec.clone(), |spec, _| more_verbose(&spec))
+}
+
+/// every level one notch more verbose, `off` and the textfilter are kept
+fn more_verbose(spec: &LogSpecification) -> LogSpecification {
+    let module_filters: Vec<ModuleFilter> = spec
+        .module_filters()
+        .iter()
+        .map(|filter| ModuleFilter {
+            module_name: filter.module_name.clone(),
+            level_filter: match filter.level_filter {
+                LevelFilter::Off => LevelFilter::Off,
+                LevelFilter::Error => LevelFilter::Warn,
+                LevelFilter::Warn => LevelFilter::Info,
+                LevelFilter::Info => LevelFilter::Debug,
+                LevelFilter::Debug | LevelFilter::Trace => LevelFilter::Trace,
+            },
+        })
+        .collect();
+    LogSpecBuilder::from_module_filters(&module_filters).build_with_textfilter(spec.text_filter().cloned())
+}
+
+#[cfg(test)]
+pub(crate) mod test {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    struct RecordingBackend(Arc<Mutex<Vec<Option<String>>>>, LogSpecification);
+
+    impl RecordingBackend {
+        fn new(applied: Arc<Mutex<Vec<Option<String>>>>) -> Self {
+            Self(applied, LogSpecification::parse("info").unwrap())
+        }
+    }
+
+    impl Backend for RecordingBackend {
+        fn set_spec(&mut self, spec: Option<&LogSpecification>) {
+            self.0.lock().unwrap().push(spec.map(|s| format!("{:?}", s.module_filters()[0].level_filter)));
+        }
+
+        fn configured_spec(&self) -> &LogSpecification {
+            &self.1
+        }
+
+        fn sinks(&self) -> Vec<SinkStats> {
+            let counters = SinkCounters::default();
+            counters.count(true);
+            counters.count(false);
+            vec![counters.stats("recording", &self.1.to_string())]
+        }
+
+        fn flush(&self) {}
+
+        fn shutdown(&mut self) {
+            self.0.lock().unwrap().push(Some("shutdown".to_string()));
+        }
+    }
+
+    pub(crate) fn register_recording_backend(handle: &ControlHandle, configured: &str) {
+        let mut backend = RecordingBackend::new(Arc::new(Mutex::new(vec![])));
+        backend.1 = LogSpecification::parse(configured).unwrap();
+        handle.register(configured, Box::new(backend));
+    }
+
+    #[test]
+    fn test_control_handle() {
+        let handle = ControlHandle::new();
+        assert_eq!(handle.current_spec(), None);
+        assert_eq!(handle.set_spec("debug"), Err(ControlError::NotStarted));
+
+        let applied = Arc::new(Mutex::new(vec![]));
+        handle.register("info", Box::new(RecordingBackend::new(applied.clone())));
+        assert_eq!(handle.current_spec().as_deref(), Some("info"));
+
+        handle.set_spec("warn").unwrap();
+        handle.push_temp_spec("trace").unwrap();
+        assert_eq!(handle.current_spec().as_deref(), Some("trace"));
+        handle.pop_temp_spec();
+        assert_eq!(handle.current_spec().as_deref(), Some("warn"));
+        assert!(matches!(handle.set_spec("debug,h2=loud"), Err(ControlError::InvalidSpec(_))));
+        assert!(matches!(handle.set_spec(" "), Err(ControlError::InvalidSpec(_))));
+        handle.reset_spec().unwrap();
+        assert_eq!(handle.current_spec().as_deref(), Some("info"));
+
+        assert_eq!(*applied.lock().unwrap(), vec![
+            Some("Warn".to_string()),
+            Some("Trace".to_string()),
+            Some("Warn".to_string()),
+            None,
+        ]);
+    }
+
+    #[test]
+    fn test_push_temp_spec_for() {
+        let handle: &'static ControlHandle = Box::leak(Box::new(ControlHandle::new()));
+        handle.register("info", Box::new(RecordingBackend::new(Arc::new(Mutex::new(vec![])))));
+        handle.push_temp_spec("warn").unwrap();
+        handle.push_temp_spec_for("debug", Duration::from_secs(300)).unwrap();
+        handle.expire_temp_specs(Instant::now());
+        assert_eq!(handle.current_spec().as_deref(), Some("debug"));
+        // the spec pushed without a duration stays
+        handle.expire_temp_specs(Instant::now() + Duration::from_secs(300));
+        assert_eq!(handle.current_spec().as_deref(), Some("warn"));
+    }
+
+    #[test]
+    fn test_shutdown() {
+        let handle = ControlHandle::new();
+        assert_eq!(handle.shutdown(Duration::from_secs(1)), Err(ControlError::NotStarted));
+        let applied = Arc::new(Mutex::new(vec![]));
+        handle.register("info", Box::new(RecordingBackend::new(applied.clone())));
+        handle.shutdown(Duration::from_secs(1)).unwrap();
+        assert_eq!(*applied.lock().unwrap(), vec![Some("shutdown".to_string())]);
+        assert_eq!(handle.current_spec(), None);
+        assert_eq!(handle.shutdown(Duration::from_secs(1)), Err(ControlError::NotStarted));
+    }
+
+    #[test]
+    fn test_register_replaces_backend() {
+        let handle = ControlHandle::new();
+        assert_eq!(handle.generation(), None);
+        let first = Arc::new(Mutex::new(vec![]));
+        let first_generation = handle.register("info", Box::new(RecordingBackend::new(first.clone())));
+        assert_eq!(handle.generation(), Some(first_generation));
+
+        let second = Arc::new(Mutex::new(vec![]));
+        let second_generation = handle.register("info", Box::new(RecordingBackend::new(second.clone())));
+        assert_ne!(first_generation, second_generation);
+        assert_eq!(*first.lock().unwrap(), vec![Some("shutdown".to_string())]);
+
+        // a guard of the replaced logger keeps the new one
+        let timeout = Duration::from_secs(1);
+        assert_eq!(handle.shutdown_generation(first_generation, timeout), Err(ControlError::NotStarted));
+        assert_eq!(*second.lock().unwrap(), vec![]);
+        assert_eq!(handle.current_spec().as_deref(), Some("info"));
+        handle.shutdown_generation(second_generation, timeout).unwrap();
+        assert_eq!(*second.lock().unwrap(), vec![Some("shutdown".to_string())]);
+        assert_eq!(handle.generation(), None);
+    }
+
+    #[test]
+    fn test_increase_verbosity_of_temp_spec() {
+        let handle = ControlHandle::new();
+        register_recording_backend(&handle, "info");
+        handle.set_spec("warn").unwrap();
+        let now = Instant::now();
+        handle.push_temp_spec_until("info", Some(now + Duration::from_secs(300))).unwrap();
+        assert_eq!(handle.increase_verbosity().as_deref(), Ok("debug"));
+        handle.expire_temp_specs(now + Duration::from_secs(60));
+        assert_eq!(handle.current_spec().as_deref(), Some("debug"));
+        // the raise reverts with the spec it raised, to the base
+        handle.expire_temp_specs(now + Duration::from_secs(300));
+        assert_eq!(handle.current_spec().as_deref(), Some("warn"));
+    }
+
+    #[test]
+    fn test_increase_verbosity() {
+        let handle = ControlHandle::new();
+        assert_eq!(handle.increase_verbosity(), Err(ControlError::NotStarted));
+        register_recording_backend(&handle, "info");
+        assert_eq!(handle.increase_verbosity().as_deref(), Ok("debug"));
+        assert_eq!(handle.increase_verbosity().as_deref(), Ok("trace"));
+        assert_eq!(handle.increase_verbosity().as_deref(), Ok("trace"));
+        handle.set_spec("warn,h2=off").unwrap();
+        assert_eq!(handle.increase_verbosity().as_deref(), Ok("info,h2=off"));
+        handle.reset_spec().unwrap();
+        assert_eq!(handle.current_spec().as_deref(), Some("info"));
+
+        // raised from the configured spec, kept under a temp spec until reset
+        assert_eq!(handle.increase_verbosity().as_deref(), Ok("debug"));
+        handle.push_temp_spec("warn").unwrap();
+        handle.pop_temp_spec();
+        assert_eq!(handle.current_spec().as_deref(), Some("debug"));
+        handle.reset_spec().unwrap();
+        assert_eq!(handle.current_spec().as_deref(), Some("info"));
+        assert_eq!(handle.reopen_files(), Ok(()));
+        assert_eq!(handle.sinks().unwrap(), vec![SinkStats {
+            name: "recording".to_string(),
+            spec: "info".to_string(),
+            written: 1,
+            failed: 1,
+        }]);
+    }
+}
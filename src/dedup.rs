@@ -0,0 +1,155 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::{Level, Record};
+
+use crate::pipeline::Stage;
+
+/// Collapses consecutive identical records (same level, target and message) into the first one
+/// followed by `last message repeated N times`, like syslogd.
+/// <br>
+/// The repeats are counted for at most `window` after the first record, the summary is sent
+/// with the next different record, the next identical record after the window, or on flush.
+/// <br>
+/// eg:
+/// ```ignore
+/// fblog::pipeline::set_deduplicator(Some(Deduplicator::new(Duration::from_secs(30))));
+/// ```
+pub struct Deduplicator {
+    window: Duration,
+    state: Mutex<Option<Last>>,
+}
+
+/// The last record passed on, and the repeats held back since.
+#[derive(Clone)]
+struct Last {
+    level: Level,
+    target: String,
+    message: String,
+    file: Option<String>,
+    line: Option<u32>,
+    since: Instant,
+    repeated: u64,
+}
+
+impl Last {
+    fn new(record: &Record, message: String, now: Instant) -> Self {
+        Self {
+            level: record.level(),
+            target: record.target().to_string(),
+            message,
+            file: record.file().map(str::to_string),
+            line: record.line(),
+            since: now,
+            repeated: 0,
+        }
+    }
+
+    fn is_repeated_by(&self, record: &Record, message: &str) -> bool {
+        self.level == record.level() && self.target == record.target() && self.message == message
+    }
+
+    fn emit_repeated(&self, next: &mut dyn FnMut(&Record)) {
+        if self.repeated == 0 {
+            return;
+        }
+        next(&Record::builder()
+            .level(self.level)
+            .target(&self.target)
+            .file(self.file.as_deref())
+            .line(self.line)
+            .args(format_args!("last message repeated {} times", self.repeated))
+            .build());
+    }
+}
+
+impl Deduplicator {
+    pub fn new(window: Duration) -> Self {
+        Self { window, state: Mutex::new(None) }
+    }
+
+    fn process_at(&self, record: &Record, now: Instant, next: &mut dyn FnMut(&Record)) {
+        let message = record.args().to_string();
+        let previous = {
+            let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            if let Some(last) = state.as_mut() {
+                if last.is_repeated_by(record, &message) && now.saturating_duration_since(last.since) < self.window {
+                    last.repeated += 1;
+                    return;
+                }
+            }
+            state.replace(Last::new(record, message, now))
+        };
+        // the lock is released, a sink may log itself
+        if let Some(previous) = previous {
+            previous.emit_repeated(next);
+        }
+        next(record);
+    }
+}
+
+impl Stage for Deduplicator {
+    fn process(&self, record: &Record, next: &mut dyn FnMut(&Record)) {
+        self.process_at(record, Instant::now(), next);
+    }
+
+    fn flush(&self, next: &mut dyn FnMut(&Record)) {
+        let repeated = {
+            let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            state.as_mut().map(|last| {
+                let repeated = last.clone();
+                last.repeated = 0;
+                repeated
+            })
+        };
+        if let Some(repeated) = repeated {
+            repeated.emit_repeated(next);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn send(dedup: &Deduplicator, level: Level, message: &str, now: Instant, passed: &mut Vec<String>) {
+        dedup.process_at(&Record::builder()
+            .level(level)
+            .target("portal")
+            .file(Some("src/db.rs"))
+            .line(Some(12))
+            .args(format_args!("{}", message))
+            .build(), now, &mut |record| passed.push(format!("{} {}", record.level(), record.args())));
+    }
+
+    #[test]
+    fn test_dedup() {
+        let dedup = Deduplicator::new(Duration::from_secs(10));
+        let start = Instant::now();
+        let mut passed = vec![];
+        for i in 0..4 {
+            send(&dedup, Level::Error, "connection refused", start + Duration::from_secs(i), &mut passed);
+        }
+        send(&dedup, Level::Warn, "connection refused", start + Duration::from_secs(4), &mut passed);
+        send(&dedup, Level::Warn, "reconnected", start + Duration::from_secs(5), &mut passed);
+        assert_eq!(passed, vec![
+            "ERROR connection refused",
+            "ERROR last message repeated 3 times",
+            "WARN connection refused",
+            "WARN reconnected",
+        ]);
+
+        // the window starts with the first record
+        passed.clear();
+        send(&dedup, Level::Warn, "reconnected", start + Duration::from_secs(9), &mut passed);
+        send(&dedup, Level::Warn, "reconnected", start + Duration::from_secs(15), &mut passed);
+        send(&dedup, Level::Warn, "reconnected", start + Duration::from_secs(16), &mut passed);
+        dedup.flush(&mut |record| passed.push(format!("{} {}", record.level(), record.args())));
+        dedup.flush(&mut |record| passed.push(format!("{} {}", record.level(), record.args())));
+        assert_eq!(passed, vec![
+            "WARN last message repeated 1 times",
+            "WARN reconnected",
+            "WARN last message repeated 1 times",
+        ]);
+    }
+}
@@ -0,0 +1,209 @@
+use std::fmt;
+
+use log::kv::{self, Key, Source, Value, VisitSource};
+
+/// SD-ID used when rendering key-values as RFC 5424 structured data.
+/// 32473 is the private enterprise number reserved for documentation (RFC 5612).
+pub const SD_ID: &str = "fblog@32473";
+
+/// Renders the key-values of a record as ` k1=v1 k2="v 2"`, with a leading space
+/// per pair so it can be appended right after the message.
+/// <br>
+/// eg: `info!(user_id = 42, room = "abc"; "joined")` -> `joined user_id=42 room=abc`
+pub struct AsText<'a>(pub &'a dyn Source);
+
+/// Renders the key-values of a record as JSON object members: `,"k1":42,"k2":"abc"`.
+/// Numbers and booleans stay unquoted, everything else is written as a JSON string.
+pub struct AsJsonFields<'a>(pub &'a dyn Source);
+
+/// Renders the key-values of a record as a single RFC 5424 SD-ELEMENT:
+/// `[fblog@32473 k1="42" k2="abc"]`, or nothing if the record has no key-values.
+pub struct AsStructuredData<'a>(pub &'a dyn Source);
+
+/// collect key-values into owned pairs, in the order they were given.
+pub fn collect(source: &dyn Source) -> Vec<(String, String)> {
+    struct Collect(Vec<(String, String)>);
+    impl<'kvs> VisitSource<'kvs> for Collect {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+            self.0.push((key.as_str().to_string(), value.to_string()));
+            Ok(())
+        }
+    }
+
+    let mut collect = Collect(Vec::with_capacity(source.count()));
+    source.visit(&mut collect).ok();
+    collect.0
+}
+
+/// Owned pairs as a Source, a pair replaces an earlier one with the same key,
+/// eg: the field of an inner log context or of a tracing event replaces the one of the outer context/span.
+pub struct OwnedPairs<'a>(pub &'a [(String, String)]);
+
+impl Source for OwnedPairs<'_> {
+    fn visit<'kvs>(&'kvs self, visitor: &mut dyn VisitSource<'kvs>) -> Result<(), kv::Error> {
+        for (i, (key, value)) in self.0.iter().enumerate() {
+            if self.0[i + 1..].iter().all(|(k, _)| k != key) {
+                visitor.visit_pair(Key::from_str(key), Value::from(value.as_str()))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// drive `f` over every pair of `source`, bridging fmt::Error and kv::Error.
+fn visit_fmt<'kvs, F>(source: &'kvs dyn Source, f: F) -> fmt::Result
+    where F: FnMut(Key<'kvs>, Value<'kvs>) -> fmt::Result
+{
+    struct Visit<F>(F);
+    impl<'kvs, F> VisitSource<'kvs> for Visit<F>
+        where F: FnMut(Key<'kvs>, Value<'kvs>) -> fmt::Result
+    {
+        fn visit_pair(&mut self, key: Key<'kvs>, value: Value<'kvs>) -> Result<(), kv::Error> {
+            (self.0)(key, value).map_err(kv::Error::from)
+        }
+    }
+
+    source.visit(&mut Visit(f)).map_err(|_| fmt::Error)
+}
+
+impl fmt::Display for AsText<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        visit_fmt(self.0, |key, value| {
+            let value = value.to_string();
+            if needs_quoting(&value) {
+                write!(f, " {}=\"{}\"", key, value.replace('\\', "\\\\").replace('"', "\\\""))
+            } else {
+                write!(f, " {}={}", key, value)
+            }
+        })
+    }
+}
+
+impl fmt::Display for AsJsonFields<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        visit_fmt(self.0, |key, value| {
+            write!(f, ",\"{}\":", JsonEscaped(key.as_str()))?;
+            write_json_value(f, &value)
+        })
+    }
+}
+
+impl fmt::Display for AsStructuredData<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.0.count() == 0 {
+            return Ok(());
+        }
+        write!(f, "[{}", SD_ID)?;
+        visit_fmt(self.0, |key, value| {
+            write!(f, " {}=\"{}\"", SdName(key.as_str()), SdParamValue(&value.to_string()))
+        })?;
+        write!(f, "]")
+    }
+}
+
+pub(crate) fn write_json_value(f: &mut fmt::Formatter<'_>, value: &Value) -> fmt::Result {
+    if let Some(b) = value.to_bool() {
+        write!(f, "{}", b)
+    } else if let Some(i) = value.to_i64() {
+        write!(f, "{}", i)
+    } else if let Some(u) = value.to_u64() {
+        write!(f, "{}", u)
+    } else if let Some(n) = value.to_f64().filter(|n| n.is_finite()) {
+        write!(f, "{}", n)
+    } else {
+        write!(f, "\"{}\"", JsonEscaped(&value.to_string()))
+    }
+}
+
+fn needs_quoting(value: &str) -> bool {
+    value.is_empty() || value.chars().any(|c| c.is_whitespace() || c == '"' || c == '=')
+}
+
+/// escapes a str to be put between the quotes of a JSON string
+pub struct JsonEscaped<'a>(pub &'a str);
+
+impl fmt::Display for JsonEscaped<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '"' => f.write_str("\\\"")?,
+                '\\' => f.write_str("\\\\")?,
+                '\n' => f.write_str("\\n")?,
+                '\r' => f.write_str("\\r")?,
+                '\t' => f.write_str("\\t")?,
+                c if (c as u32) < 0x20 => write!(f, "\\u{:04x}", c as u32)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// SD-NAME: printable US-ASCII except '=', ' ', ']' and '"', at most 32 chars.
+struct SdName<'a>(&'a str);
+
+impl fmt::Display for SdName<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars().take(32) {
+            match c {
+                '=' | ' ' | ']' | '"' => f.write_str("_")?,
+                c if c.is_ascii_graphic() => write!(f, "{}", c)?,
+                _ => f.write_str("_")?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// PARAM-VALUE: '"', '\' and ']' must be escaped with '\'.
+struct SdParamValue<'a>(&'a str);
+
+impl fmt::Display for SdParamValue<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for c in self.0.chars() {
+            match c {
+                '"' | '\\' | ']' => write!(f, "\\{}", c)?,
+                c => write!(f, "{}", c)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn render<F: Fn(&dyn Source) -> String>(f: F) -> String {
+        let kvs: &[(&str, Value)] = &[
+            ("user_id", Value::from(42)),
+            ("room", Value::from("abc")),
+            ("note", Value::from("say \"hi\"]")),
+        ];
+        f(&kvs)
+    }
+
+    #[test]
+    fn test_as_text() {
+        assert_eq!(render(|s| AsText(s).to_string()), r#" user_id=42 room=abc note="say \"hi\"]""#);
+        let empty: &[(&str, Value)] = &[];
+        assert_eq!(AsText(&empty).to_string(), "");
+    }
+
+    #[test]
+    fn test_as_json_fields() {
+        assert_eq!(render(|s| AsJsonFields(s).to_string()), r#","user_id":42,"room":"abc","note":"say \"hi\"]""#);
+    }
+
+    #[test]
+    fn test_as_structured_data() {
+        assert_eq!(render(|s| AsStructuredData(s).to_string()), r#"[fblog@32473 user_id="42" room="abc" note="say \"hi\"\]"]"#);
+        let empty: &[(&str, Value)] = &[];
+        assert_eq!(AsStructuredData(&empty).to_string(), "");
+    }
+
+    #[test]
+    fn test_collect() {
+        assert_eq!(render(|s| format!("{:?}", collect(s))), r#"[("user_id", "42"), ("room", "abc"), ("note", "say \"hi\"]")]"#);
+    }
+}
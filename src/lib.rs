@@ -1,11 +1,34 @@
 use std::collections::HashSet;
 use std::fmt::Debug;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::str::FromStr;
 
 use gethostname;
+use log::LevelFilter;
 use pnet::datalink;
 pub use syslog::Facility;
+// used by the ctx_*! macros
+#[doc(hidden)]
+pub use log;
+
+#[cfg(feature = "clap")]
+pub use cli::LogArgs;
+pub use config_for_flexi_logger::FileRoute;
+use config_for_flexi_logger::LocalSinks;
+pub use context::LogContext;
+#[cfg(feature = "tokio")]
+pub use context::with_context;
+pub use control::{handle, shutdown, ControlError, ControlHandle, ShutdownGuard};
+pub use dedup::Deduplicator;
+pub use panic_hook::install_panic_hook;
+pub use parse::ParsedRecord;
+pub use rate_limit::{Limits, RateLimiter};
+pub use redact::{Builtin, Redactor};
+pub use ring_buffer::RingBuffer;
+pub use syslog_codes::{SeverityMap, SyslogFacility, SyslogSeverity};
+#[cfg(feature = "tracing")]
+pub use tracing_layer::FblogLayer;
 
 pub use config_for_env_logger::get_default_env_logger_builder;
 
@@ -14,11 +37,34 @@ pub use config_for_env_logger::get_default_env_logger_builder;
 // pub use config_for_flexi_logger::*;
 // pub use config_for_syslog::{start_udp_logger, start_udp_logger_in_test};
 
+#[cfg(feature = "admin")]
+pub mod admin;
+#[cfg(feature = "clap")]
+pub mod cli;
+pub mod color;
 pub mod config_for_env_logger;
 pub mod config_for_flexi_logger;
 pub mod config_for_syslog;
+pub mod context;
+pub mod control;
+pub mod dedup;
+pub mod key_values;
+pub mod panic_hook;
+pub mod parse;
+pub mod pipeline;
+pub mod rate_limit;
+pub mod redact;
+pub mod ring_buffer;
+#[cfg(all(unix, feature = "signals"))]
+pub mod signals;
+pub mod syslog_codes;
+#[cfg(any(test, feature = "test-helpers"))]
+pub mod syslog_receiver;
+pub mod template;
 pub mod test_helper;
 pub mod toolbox;
+#[cfg(feature = "tracing")]
+pub mod tracing_layer;
 
 pub fn hostname() -> String {
     gethostname::gethostname().to_string_lossy().to_string()
@@ -132,18 +178,26 @@ pub fn str_to_socket_addr(s: &str) -> SocketAddr {
 pub struct SysLog {
     facility: Facility,
     remote_address: SocketAddr,
+    severities: SeverityMap,
 }
 
 impl SysLog {
     pub fn new(facility: Facility, remote_address: SocketAddr) -> Self {
         Self {
             facility,
-            remote_address
+            remote_address,
+            severities: SeverityMap::default(),
         }
     }
 
-    pub fn start_udp_logger(&self, log_spec: &str, process_name: &str) {
-        start_udp_logger(self.facility, self.remote_address, log_spec, process_name)
+    /// the syslog severity of each level, eg: "error=crit,info=notice".parse()?
+    pub fn with_severity_map(mut self, severities: SeverityMap) -> Self {
+        self.severities = severities;
+        self
+    }
+
+    pub fn start_udp_logger(&self, log_spec: &str, process_name: &str) -> ShutdownGuard {
+        start_udp_logger_with_severity_map(self.facility, self.remote_address, log_spec, process_name, self.severities.clone())
     }
 }
 
@@ -151,6 +205,11 @@ impl SysLog {
 pub struct LocalLog {
     enabled_console_log: bool,
     enabled_file_log: bool,
+    console_log_spec: Option<String>,
+    file_log_spec: Option<String>,
+    stderr_level: LevelFilter,
+    file_routes: Vec<FileRoute>,
+    log_dir: Option<PathBuf>,
 }
 
 impl LocalLog {
@@ -162,11 +221,67 @@ impl LocalLog {
         Self {
             enabled_console_log,
             enabled_file_log,
+            console_log_spec: None,
+            file_log_spec: None,
+            stderr_level: LevelFilter::Off,
+            file_routes: vec![],
+            log_dir: None,
         }
     }
 
-    pub fn start_local_logger(&self, log_spec: &str) {
-        start_local_logger(log_spec, self.enabled_console_log, self.enabled_file_log)
+    /// use `log_spec` for the console instead of the one given to start_local_logger, eg: "warn" in production
+    pub fn with_console_log_spec(mut self, log_spec: &str) -> Self {
+        self.console_log_spec = Some(log_spec.to_string());
+        self
+    }
+
+    /// use `log_spec` for the log file instead of the one given to start_local_logger, eg: "debug,h2=info"
+    pub fn with_file_log_spec(mut self, log_spec: &str) -> Self {
+        self.file_log_spec = Some(log_spec.to_string());
+        self
+    }
+
+    /// console records at or above `level` go to stderr instead of stdout, eg: LevelFilter::Error
+    pub fn with_stderr_level(mut self, level: LevelFilter) -> Self {
+        self.stderr_level = level;
+        self
+    }
+
+    /// write records matching `route` to their own file next to the main log file, eg:
+    /// FileRoute::for_targets("audit", &["audit"]), FileRoute::for_level("error", LevelFilter::Error).also_in_main_log()
+    pub fn with_file_route(mut self, route: FileRoute) -> Self {
+        self.file_routes.push(route);
+        self
+    }
+
+    /// write the log files in `log_dir` instead of the current directory
+    pub fn with_log_dir(mut self, log_dir: impl Into<PathBuf>) -> Self {
+        self.log_dir = Some(log_dir.into());
+        self
+    }
+
+    /// `log_spec` is used for every enabled sink without its own spec.
+    pub fn start_local_logger(&self, log_spec: &str) -> ShutdownGuard {
+        println!("Using triditional console/file log");
+        config_for_flexi_logger::start_local_logger(&self.local_sinks(log_spec));
+        ShutdownGuard::new()
+    }
+
+    fn local_sinks(&self, log_spec: &str) -> LocalSinks {
+        let sink_spec = |enabled: bool, spec: &Option<String>| {
+            if enabled {
+                Some(spec.clone().unwrap_or_else(|| log_spec.to_string()))
+            } else {
+                None
+            }
+        };
+        LocalSinks {
+            console_log_spec: sink_spec(self.enabled_console_log, &self.console_log_spec),
+            file_log_spec: sink_spec(self.enabled_file_log, &self.file_log_spec),
+            stderr_level: self.stderr_level,
+            file_routes: self.file_routes.clone(),
+            log_dir: self.log_dir.clone(),
+        }
     }
 }
 
@@ -174,7 +289,16 @@ pub fn start_udp_logger(
     facility: Facility,
     remote_address: SocketAddr,
     log_spec: &str,
-    process_name: &str) {
+    process_name: &str) -> ShutdownGuard {
+    start_udp_logger_with_severity_map(facility, remote_address, log_spec, process_name, SeverityMap::default())
+}
+
+fn start_udp_logger_with_severity_map(
+    facility: Facility,
+    remote_address: SocketAddr,
+    log_spec: &str,
+    process_name: &str,
+    severities: SeverityMap) -> ShutdownGuard {
     println!("Try starting udp logger with process_name: {:?}, log_spec: {:?}", process_name, log_spec);
     // remote syslog udp server is: 514, so we using 15514 as local
     let local_address = SocketAddr::from_str("0.0.0.0:0")
@@ -182,27 +306,62 @@ pub fn start_udp_logger(
             println!("Failed to parse socket address, err: {}", err);
         })
         .expect("failed to parse sockt address");
-    let log_level = config_for_syslog::get_formal_log_level_from_str(log_spec);
-    println!("Final sys_log_level: {:?}, local_address: {:?}, remote_address: {:?}", log_level, local_address, remote_address);
-    config_for_syslog::start_udp_logger(facility, process_name, local_address, remote_address, log_level);
+    let spec = syslog_log_spec(log_spec);
+    println!("Final sys_log_spec: {}, local_address: {:?}, remote_address: {:?}", spec.to_string(), local_address, remote_address);
+    config_for_syslog::start_udp_logger_with_severity_map(facility, process_name, local_address, remote_address, spec, severities);
     println!("Started udp logger @{}", chrono::Local::now());
+    ShutdownGuard::new()
+}
+
+/// module filters like "info,h2=warn" are kept,
+/// an empty or unparsable spec falls back to a single level (info if none is recognized)
+fn syslog_log_spec(log_spec: &str) -> flexi_logger::LogSpecification {
+    let single_level = || {
+        let log_level = config_for_syslog::get_formal_log_level_from_str(log_spec.trim());
+        flexi_logger::LogSpecBuilder::new().default(log_level).build()
+    };
+    // LogSpecification parses "" as all off
+    if log_spec.trim().is_empty() {
+        return single_level();
+    }
+    flexi_logger::LogSpecification::parse(log_spec).unwrap_or_else(|_| single_level())
 }
 
 pub fn start_local_logger(log_spec: &str,
                           enabled_console_log: bool,
                           enabled_file_log: bool,
-) {
-    println!("Using triditional console/file log");
+) -> ShutdownGuard {
     if enabled_console_log || enabled_file_log {
-        config_for_flexi_logger::start_default_logger(log_spec, enabled_console_log, enabled_file_log);
+        LocalLog::new(enabled_console_log, enabled_file_log).start_local_logger(log_spec)
     } else {
         panic!("Use local log, but no console-log or file-log is specified!")
     }
 }
 
 
-/// 1. try udp logger(default) (use Facility::LOG_USER if not supplied)
+/// same as `start_logger_automatically`, with the facility named by `facility_if_udp_enabled`, eg: "local1" (LOG_USER if empty)
+pub fn start_logger_automatically_with_facility_name(
+    process_name: &str,
+    log_spec: &str,
+    enabled_udp_logger_arg: &str,
+    udp_server_address_if_udp_enabled: &str,
+    facility_if_udp_enabled: &str,
+    enabled_local_console_log_arg: &str,
+    enabled_local_file_log_arg: &str) -> ShutdownGuard {
+    let facility = match facility_if_udp_enabled.trim() {
+        "" => None,
+        name => Some(name.parse::<SyslogFacility>()
+            .unwrap_or_else(|err| panic!("Parse syslog facility error from input str: {:?}, err: {}", name, err))
+            .into()),
+    };
+    start_logger_automatically(process_name, log_spec, enabled_udp_logger_arg, udp_server_address_if_udp_enabled, facility,
+                               enabled_local_console_log_arg, enabled_local_file_log_arg)
+}
+
+/// 1. try udp logger(default) with `facility_if_udp_enabled` (LOG_USER if None)
 /// 2. try local logger(console-logger or file-logger)
+///
+/// the returned guard flushes and shuts down the logger when dropped, keep it until the end of `main`.
 pub fn start_logger_automatically(
     process_name: &str,
     log_spec: &str,
@@ -210,7 +369,7 @@ pub fn start_logger_automatically(
     udp_server_address_if_udp_enabled: &str,
     facility_if_udp_enabled: Option<Facility>,
     enabled_local_console_log_arg: &str,
-    enabled_local_file_log_arg: &str) {
+    enabled_local_file_log_arg: &str) -> ShutdownGuard {
     println!(r##"Try starting logger automatically,
 process_name: {:?},
 log_spec: {:?},
@@ -223,14 +382,14 @@ enabled_local_file_log_arg: {:?}
     let enabled_udp_logger = toolbox::is_bool_true(enabled_udp_logger_arg);
     if enabled_udp_logger {
         println!("Using syslog(udp) by parsing cli");
-        SysLog::new(facility_if_udp_enabled.unwrap_or(Facility::LOG_USER),str_to_socket_addr(udp_server_address_if_udp_enabled))
-            .start_udp_logger(log_spec, process_name);
+        SysLog::new(facility_if_udp_enabled.unwrap_or(Facility::LOG_USER), str_to_socket_addr(udp_server_address_if_udp_enabled))
+            .start_udp_logger(log_spec, process_name)
     } else {
         println!("Using traditional console/file log by parsing cli");
         let enable_local_console_log = toolbox::is_bool_true(enabled_local_console_log_arg);
         let enable_local_file_log = toolbox::is_bool_true(enabled_local_file_log_arg);
         LocalLog::new(enable_local_console_log, enable_local_file_log)
-            .start_local_logger(log_spec);
+            .start_local_logger(log_spec)
     }
 }
 
@@ -262,24 +421,18 @@ enabled_local_file_log_arg: {:?}
 //     }
 // }
 
-#[macro_export]
-macro_rules! ctx_debug {
-    (target: $target:expr, $($arg:tt)+) => (
-        log!(target: $target, log::Level::Debug, $($arg)+)
-    );
-    ($($arg:tt)+) => (
-        log!(log::Level::Debug, $($arg)+)
-    )
-}
-
 #[cfg(test)]
 mod test {
+    use std::time::Duration;
+
     use log::*;
 
     use super::*;
+    use crate::test_helper::SyslogReceiver;
 
     #[test]
     fn test_ctx_debug() {
+        let _lock = test_helper::logger_lock();
         test_helper::try_init_logger("trace");
         ctx_debug!("{}", 10);
     }
@@ -292,42 +445,95 @@ mod test {
 
     #[test]
     fn test_start_logger_automatically_udp() {
-        start_logger_automatically("process_name",
+        let _lock = test_helper::logger_lock();
+        let receiver = SyslogReceiver::udp().unwrap();
+        let _guard = start_logger_automatically_with_facility_name("process_name",
                                    "debug",
                                    "true",
-                                   "127.0.0.1:514",
-                                   None,
+                                   &receiver.local_addr().unwrap().to_string(),
+                                   "local1",
                                    "false",
                                    "false");
+        info!("started automatically");
+        let received = receiver.wait_until(Duration::from_secs(3), |message| message.message == "started automatically");
+        let last = received.last().map(|message| (SyslogFacility(message.facility), message.severity_name(), message.message.as_str()));
+        assert_eq!(last, Some((SyslogFacility(Facility::LOG_LOCAL1), "info", "started automatically")), "{:?}", received);
     }
 
 
     #[test]
     fn test_start_logger_automatically_local_console_and_local_file() {
+        let _lock = test_helper::logger_lock();
         let log_spec = "debug";
 
-        LocalLog::new(true, true).start_local_logger(log_spec);
+        let _guard = LocalLog::new(true, true).start_local_logger(log_spec);
 
         info!("info");
     }
 
     #[test]
     fn test_start_logger_automatically_local_console_only() {
+        let _lock = test_helper::logger_lock();
         let log_spec = "debug";
 
-        LocalLog::new(true, false).start_local_logger(log_spec);
+        let _guard = LocalLog::new(true, false).start_local_logger(log_spec);
 
         info!("info");
     }
 
+    #[test]
+    fn test_local_log_sink_specs() {
+        let sinks = LocalLog::new(true, true)
+            .with_console_log_spec("warn")
+            .with_stderr_level(LevelFilter::Error)
+            .local_sinks("debug");
+        assert_eq!(sinks.console_log_spec.as_deref(), Some("warn"));
+        assert_eq!(sinks.file_log_spec.as_deref(), Some("debug"));
+        assert_eq!(sinks.stderr_level, LevelFilter::Error);
+
+        let sinks = LocalLog::new(false, true).with_console_log_spec("warn").local_sinks("info");
+        assert_eq!(sinks.console_log_spec, None);
+        assert_eq!(sinks.file_log_spec.as_deref(), Some("info"));
+    }
+
     #[test]
     fn test_start_logger_automatically_local_file_only() {
+        let _lock = test_helper::logger_lock();
         let log_spec = "debug";
 
-        LocalLog::new(false, true).start_local_logger(log_spec);
+        let _guard = LocalLog::new(false, true).start_local_logger(log_spec);
 
         info!("info");
     }
+
+    #[test]
+    fn test_syslog_log_spec() {
+        assert_eq!(syslog_log_spec("").to_string(), "info");
+        assert_eq!(syslog_log_spec(" \n").to_string(), "info");
+        assert_eq!(syslog_log_spec("debug,h2=warn").to_string(), "debug,h2=warn");
+        assert_eq!(syslog_log_spec("warn,h2=loud").to_string(), "warn");
+        assert_eq!(syslog_log_spec("off").to_string(), "off");
+    }
+
+    #[test]
+    fn test_shutdown_flushes_stages() {
+        let _lock = test_helper::logger_lock();
+        let log_dir = std::env::temp_dir().join(format!("fblog-test-shutdown-{}", std::process::id()));
+        let guard = LocalLog::new(false, true).with_log_dir(&log_dir).start_local_logger("info");
+        pipeline::set_deduplicator(Some(dedup::Deduplicator::new(Duration::from_secs(60))));
+        for _ in 0..3 {
+            info!(target: "shutdown_test", "disk almost full");
+        }
+        drop(guard);
+        pipeline::set_deduplicator(None);
+
+        let written: String = std::fs::read_dir(&log_dir).unwrap()
+            .map(|entry| std::fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        std::fs::remove_dir_all(&log_dir).ok();
+        assert_eq!(written.matches("disk almost full").count(), 1);
+        assert!(written.contains("last message repeated 2 times"), "{}", written);
+    }
 }
 
 
@@ -0,0 +1,82 @@
+use std::any::Any;
+use std::backtrace::Backtrace;
+
+use log::*;
+
+/// Log panics at `error` level through the started logger (syslog or console/file),
+/// so they are not lost when stderr is not collected, eg:
+/// <br>
+/// ```[ERROR] ... src/job.rs:42 panicked: index out of bounds ... thread=worker-1```
+/// <br>
+/// The record has target "panic", the file and line of the panic, and carries the captured backtrace.
+/// The logger is flushed afterwards, then the previous hook (the default one prints to stderr) is called.
+/// <br>
+/// Call it after starting the logger.
+pub fn install_panic_hook() {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        let thread = std::thread::current();
+        let thread_name = thread.name().unwrap_or("<unnamed>");
+        let kvs: &[(&str, &str)] = &[("thread", thread_name)];
+        let backtrace = Backtrace::force_capture();
+        // file and line of the record are where the panic happened, not here
+        log::logger().log(&Record::builder()
+            .level(Level::Error)
+            .target("panic")
+            .file(info.location().map(|location| location.file()))
+            .line(info.location().map(|location| location.line()))
+            .key_values(&kvs)
+            .args(format_args!("panicked: {}\nbacktrace:\n{}", panic_message(info.payload()), backtrace))
+            .build());
+        log::logger().flush();
+        previous(info);
+    }));
+}
+
+/// the message given to `panic!`, for the payloads of `panic!("literal")` and `panic!("{}", formatted)`
+fn panic_message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message.as_str()
+    } else {
+        "Box<dyn Any>"
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_panic_message() {
+        let payload = std::panic::catch_unwind(|| panic!("static")).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "static");
+        let payload = std::panic::catch_unwind(|| panic!("formatted {}", 42)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "formatted 42");
+        let payload = std::panic::catch_unwind(|| std::panic::panic_any(42)).unwrap_err();
+        assert_eq!(panic_message(payload.as_ref()), "Box<dyn Any>");
+    }
+
+    #[test]
+    fn test_panic_hook() {
+        let capture = crate::test_helper::capture();
+        // the hook is process-wide, the previous one is put back afterwards
+        let previous = std::panic::take_hook();
+        install_panic_hook();
+        let line = line!() + 1;
+        let result = std::panic::catch_unwind(|| panic!("formatted {}", 42));
+        std::panic::set_hook(previous);
+        assert!(result.is_err());
+
+        let records = capture.records();
+        assert_eq!(records.len(), 1);
+        let record = &records[0];
+        assert_eq!((record.level, record.target.as_str()), (Level::Error, "panic"));
+        assert_eq!((record.file.as_deref(), record.line), (Some(file!()), Some(line)));
+        assert_eq!(record.key_value("thread"), std::thread::current().name());
+        assert!(record.message.starts_with("panicked: formatted 42\nbacktrace:\n"));
+        assert!(record.message.contains("test_panic_hook"));
+        assert_eq!(capture.flushes(), 1);
+    }
+}
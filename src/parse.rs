@@ -0,0 +1,668 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDateTime, TimeZone};
+use log::Level;
+use once_cell::sync::Lazy;
+use regex::Regex;
+use syslog::Facility;
+
+use crate::key_values::SD_ID;
+use crate::syslog_codes::{SyslogFacility, SyslogSeverity};
+
+/// `[INFO] 2016-01-13 15:25:01.640870 +08:00 host-10.0.0.1 foo::bar src/foo/bar.rs:26 msg k=v`
+static TEXT_LINE: Lazy<Regex> = Lazy::new(|| Regex::new(concat!(
+    r"^\[(ERROR|WARN|INFO|DEBUG|TRACE)\] ",
+    r"(\d{4}-\d{2}-\d{2} \d{2}:\d{2}:\d{2}(?:\.\d+)? [+-]\d{2}:\d{2}(?::\d{2})?) ",
+    r"(\S+) (\S+) (\S+):(\d+)(?: (.*))?$",
+)).unwrap());
+
+/// the last ` key=value` of a text line, the value quoted as `AsText` does
+static TRAILING_KEY_VALUE: Lazy<Regex> = Lazy::new(|| Regex::new(r#" ([^\s="]+)=("(?:[^"\\]|\\.)*"|[^\s"=]+)$"#).unwrap());
+
+/// the colors of the console lines
+static ANSI_STYLE: Lazy<Regex> = Lazy::new(|| Regex::new(r"\x1b\[[0-9;]*m").unwrap());
+
+/// The format a line was parsed from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LineFormat {
+    /// `detailed_format` and `get_default_env_logger_builder`, with the default template
+    Text,
+    /// `json_format` and `get_json_env_logger_builder`
+    Json,
+    /// what the syslog sink sends: RFC 3164, or RFC 5424 for the records with key-values
+    Syslog,
+}
+
+/// A record read back from a log line, eg:
+/// ```ignore
+/// let record = fblog::parse::parse_line(&line).ok_or("not a log line")?;
+/// if record.level <= Level::Warn && record.key_value("user_id") == Some("42") { ... }
+/// ```
+/// The fields the format doesn't have, or written as `<unnamed>`, are None.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParsedRecord {
+    pub format: LineFormat,
+    pub level: Level,
+    pub time: Option<DateTime<FixedOffset>>,
+    pub host: Option<String>,
+    pub ip: Option<String>,
+    /// text and json only
+    pub module: Option<String>,
+    /// text and json only
+    pub file: Option<String>,
+    /// text and json only
+    pub line: Option<u32>,
+    pub message: String,
+    /// strings unquoted, the other values as written
+    /// <br>
+    /// Text lines only: guessed from the end of the message, see `parse_text`.
+    pub key_values: Vec<(String, String)>,
+    /// syslog only
+    pub facility: Option<SyslogFacility>,
+    /// syslog only, `level` is the level it maps back to, eg: notice -> info
+    pub severity: Option<SyslogSeverity>,
+    /// syslog only
+    pub process: Option<String>,
+    /// syslog only
+    pub pid: Option<u32>,
+}
+
+impl ParsedRecord {
+    fn new(format: LineFormat, level: Level, message: String) -> Self {
+        ParsedRecord {
+            format,
+            level,
+            time: None,
+            host: None,
+            ip: None,
+            module: None,
+            file: None,
+            line: None,
+            message,
+            key_values: vec![],
+            facility: None,
+            severity: None,
+            process: None,
+            pid: None,
+        }
+    }
+
+    /// the value of `key` in the key-values
+    pub fn key_value(&self, key: &str) -> Option<&str> {
+        self.key_values.iter().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+}
+
+/// Parse a line in any of the formats, detected by its first character, the colors of the console are ignored.
+pub fn parse_line(line: &str) -> Option<ParsedRecord> {
+    let line = ANSI_STYLE.replace_all(line, "");
+    let line = line.trim_end_matches(['\n', '\r']);
+    match line.chars().next()? {
+        '{' => parse_json(line),
+        '<' => parse_syslog(line),
+        _ => parse_text(line),
+    }
+}
+
+/// Parse a line laid out by the default template (see `template::DEFAULT_TEMPLATE`).
+/// <br>
+/// The ` key=value` words at the end of the message are taken as the key-values of the record,
+/// as the template writes them right after the message.
+/// <br>
+/// Nothing tells them apart from the message, so a message ending with `key=value` words loses them to the key-values,
+/// eg: `info!("connected host=db1")` is read back as `connected` with `host=db1`.
+/// Use the json or syslog format when the key-values must come back exactly.
+pub fn parse_text(line: &str) -> Option<ParsedRecord> {
+    let caps = TEXT_LINE.captures(line.trim_end_matches(['\n', '\r']))?;
+    let (message, key_values) = split_key_values(caps.get(7).map_or("", |msg| msg.as_str()));
+    let mut record = ParsedRecord::new(LineFormat::Text, Level::from_str(&caps[1]).ok()?, message.to_string());
+    record.time = parse_time(&caps[2]);
+    // the host name may contain '-', the ip can't
+    let (host, ip) = match caps[3].rsplit_once('-') {
+        Some((host, ip)) => (host, Some(ip)),
+        None => (&caps[3], None),
+    };
+    record.host = Some(host.to_string());
+    record.ip = ip.map(str::to_string);
+    record.module = named(&caps[4]);
+    // absolute paths are written as urls
+    record.file = named(caps[5].strip_prefix("file://").unwrap_or(&caps[5]));
+    record.line = caps[6].parse().ok().filter(|line| *line != 0);
+    record.key_values = key_values;
+    Some(record)
+}
+
+/// Parse a JSON line, the fields other than level, time, host, ip, module, file, line and msg are the key-values.
+pub fn parse_json(line: &str) -> Option<ParsedRecord> {
+    let mut fields = parse_json_object(line)?;
+    let mut take = |name: &str| fields.iter().position(|(key, _)| key == name).map(|i| fields.remove(i).1);
+    let level = Level::from_str(&take("level")?).ok()?;
+    let mut record = ParsedRecord::new(LineFormat::Json, level, take("msg")?);
+    record.time = take("time").and_then(|time| parse_time(&time));
+    record.host = take("host").and_then(|host| named(&host));
+    record.ip = take("ip").and_then(|ip| named(&ip));
+    record.module = take("module").and_then(|module| named(&module));
+    record.file = take("file").and_then(|file| named(&file));
+    record.line = take("line").and_then(|line| line.parse().ok()).filter(|line| *line != 0);
+    record.key_values = fields;
+    Some(record)
+}
+
+/// Parse a syslog message, the key-values are read back from the RFC 5424 structured data,
+/// or from the one in front of the message of an RFC 3164 message (as sent by earlier versions of the syslog sink).
+/// <br>
+/// RFC 3164 timestamps have no year, they are taken as the last such time in the local time zone.
+pub fn parse_syslog(line: &str) -> Option<ParsedRecord> {
+    let message = SyslogMessage::parse(line.trim_end_matches(['\n', '\r']))?;
+    let (key_values, text) = match message.structured_data.as_deref() {
+        // RFC 5424
+        Some(structured_data) => (parse_structured_data(structured_data).map_or(vec![], |(key_values, _)| key_values), message.message.as_str()),
+        None => parse_structured_data(&message.message).unwrap_or((vec![], &message.message)),
+    };
+    let mut record = ParsedRecord::new(LineFormat::Syslog, level_of(message.severity), text.to_string());
+    record.time = message.timestamp.as_deref().and_then(|timestamp| {
+        DateTime::parse_from_rfc3339(timestamp).ok().or_else(|| parse_3164_time(timestamp))
+    });
+    // the syslog sink sends "<host>_<ip>" as hostname
+    if let Some(hostname) = message.hostname {
+        match hostname.rsplit_once('_') {
+            Some((host, ip)) => {
+                record.host = Some(host.to_string());
+                record.ip = Some(ip.to_string());
+            }
+            None => record.host = Some(hostname),
+        }
+    }
+    record.key_values = key_values;
+    record.facility = Some(SyslogFacility(message.facility));
+    record.severity = Some(message.severity);
+    record.process = message.process;
+    record.pid = message.pid;
+    Some(record)
+}
+
+/// Parse the time of a text or json line, as written by the default template, chrono or time, eg:
+/// `2016-01-13 15:25:01.640870 +08:00`, `2016-01-13 15:25:01.64087 +08:00:00`, or RFC 3339.
+pub fn parse_time(s: &str) -> Option<DateTime<FixedOffset>> {
+    DateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S%.f %:z")
+        // time writes the seconds of the offset
+        .or_else(|_| DateTime::parse_from_str(s.strip_suffix(":00").unwrap_or(s), "%Y-%m-%d %H:%M:%S%.f %:z"))
+        .or_else(|_| DateTime::parse_from_rfc3339(s))
+        .ok()
+}
+
+/// "Oct 19 08:03:29" or "Oct  9 08:03:29"
+fn parse_3164_time(timestamp: &str) -> Option<DateTime<FixedOffset>> {
+    let now = Local::now();
+    let timestamp = timestamp.split_whitespace().collect::<Vec<_>>().join(" ");
+    let at_year = |year: i32| {
+        NaiveDateTime::parse_from_str(&format!("{} {}", year, timestamp), "%Y %b %d %H:%M:%S")
+            .ok()
+            .and_then(|naive| Local.from_local_datetime(&naive).earliest())
+    };
+    let time = at_year(now.year())?;
+    // sent last year, eg: read on Jan 1 from Dec 31
+    let time = if time > now + chrono::Duration::days(1) { at_year(now.year() - 1)? } else { time };
+    Some(time.fixed_offset())
+}
+
+/// the level `SeverityMap::default()` maps to the severity, the severities above err are errors too
+fn level_of(severity: SyslogSeverity) -> Level {
+    match severity {
+        SyslogSeverity::Emerg | SyslogSeverity::Alert | SyslogSeverity::Crit | SyslogSeverity::Err => Level::Error,
+        SyslogSeverity::Warning => Level::Warn,
+        SyslogSeverity::Notice | SyslogSeverity::Info => Level::Info,
+        SyslogSeverity::Debug => Level::Debug,
+    }
+}
+
+/// None for the placeholders of the missing fields
+fn named(value: &str) -> Option<String> {
+    (!value.is_empty() && value != "<unnamed>").then(|| value.to_string())
+}
+
+fn split_key_values(text: &str) -> (&str, Vec<(String, String)>) {
+    let mut rest = text;
+    let mut key_values = vec![];
+    while let Some(caps) = TRAILING_KEY_VALUE.captures(rest) {
+        let value = match caps[2].strip_prefix('"').and_then(|value| value.strip_suffix('"')) {
+            Some(quoted) => unescape(quoted),
+            None => caps[2].to_string(),
+        };
+        key_values.push((caps[1].to_string(), value));
+        rest = &rest[..caps.get(0).unwrap().start()];
+    }
+    key_values.reverse();
+    (rest, key_values)
+}
+
+/// drop the `\` escaping the next character
+fn unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => unescaped.extend(chars.next()),
+            c => unescaped.push(c),
+        }
+    }
+    unescaped
+}
+
+/// `[fblog@32473 user_id="42" room="abc"] rest` -> the key-values and `rest`
+fn parse_structured_data(text: &str) -> Option<(Vec<(String, String)>, &str)> {
+    let mut rest = text.strip_prefix('[')?.strip_prefix(SD_ID)?;
+    let mut key_values = vec![];
+    loop {
+        rest = rest.trim_start_matches(' ');
+        if let Some(after) = rest.strip_prefix(']') {
+            return Some((key_values, after.strip_prefix(' ').unwrap_or(after)));
+        }
+        let (name, after) = rest.split_once("=\"")?;
+        let mut escaped = false;
+        let end = after.char_indices().find_map(|(i, c)| match c {
+            _ if escaped => {
+                escaped = false;
+                None
+            }
+            '\\' => {
+                escaped = true;
+                None
+            }
+            '"' => Some(i),
+            _ => None,
+        })?;
+        key_values.push((name.to_string(), unescape(&after[..end])));
+        rest = &after[end + 1..];
+    }
+}
+
+/// the fields of a flat JSON object, strings unescaped, the other values as written
+fn parse_json_object(s: &str) -> Option<Vec<(String, String)>> {
+    let mut chars = s.trim().chars().peekable();
+    let mut fields = vec![];
+    let skip_whitespace = |chars: &mut std::iter::Peekable<std::str::Chars>| {
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+    };
+    if chars.next()? != '{' {
+        return None;
+    }
+    skip_whitespace(&mut chars);
+    if chars.next_if_eq(&'}').is_some() {
+        return Some(fields);
+    }
+    loop {
+        skip_whitespace(&mut chars);
+        let key = parse_json_string(&mut chars)?;
+        skip_whitespace(&mut chars);
+        if chars.next()? != ':' {
+            return None;
+        }
+        skip_whitespace(&mut chars);
+        let value = if chars.peek() == Some(&'"') {
+            parse_json_string(&mut chars)?
+        } else {
+            let mut value = String::new();
+            while let Some(c) = chars.next_if(|c| !matches!(c, ',' | '}') && !c.is_whitespace()) {
+                value.push(c);
+            }
+            if value.is_empty() {
+                return None;
+            }
+            value
+        };
+        fields.push((key, value));
+        skip_whitespace(&mut chars);
+        match chars.next()? {
+            ',' => continue,
+            '}' => break,
+            _ => return None,
+        }
+    }
+    chars.all(char::is_whitespace).then_some(fields)
+}
+
+fn parse_json_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> Option<String> {
+    if chars.next()? != '"' {
+        return None;
+    }
+    let mut s = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(s),
+            '\\' => match chars.next()? {
+                'n' => s.push('\n'),
+                'r' => s.push('\r'),
+                't' => s.push('\t'),
+                'b' => s.push('\u{8}'),
+                'f' => s.push('\u{c}'),
+                'u' => {
+                    let code: String = chars.by_ref().take(4).collect();
+                    s.push(u32::from_str_radix(&code, 16).ok().and_then(char::from_u32).unwrap_or('\u{fffd}'));
+                }
+                c => s.push(c),
+            },
+            c => s.push(c),
+        }
+    }
+}
+
+const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+/// A syslog message (RFC 3164 or RFC 5424), as read by `parse_syslog` or received by `test_helper::SyslogReceiver`.
+#[derive(Debug, Clone)]
+pub struct SyslogMessage {
+    pub facility: Facility,
+    pub severity: SyslogSeverity,
+    pub timestamp: Option<String>,
+    pub hostname: Option<String>,
+    pub process: Option<String>,
+    pub pid: Option<u32>,
+    /// RFC 5424 only
+    pub msgid: Option<String>,
+    /// RFC 5424 only, eg: `[fblog@32473 user_id="42"]`
+    pub structured_data: Option<String>,
+    pub message: String,
+}
+
+impl SyslogMessage {
+    /// eg: "err", "warning", "debug"
+    pub fn severity_name(&self) -> &'static str {
+        self.severity.name()
+    }
+
+    /// Parse a message as sent by syslog senders, eg:
+    /// <br>
+    /// ```<11>Oct 19 08:03:29 host_10.0.0.7N24 portal[42]: connection lost```
+    /// <br>
+    /// ```<11>1 2026-10-19T08:03:29Z host portal 42 - - connection lost```
+    pub fn parse(text: &str) -> Option<SyslogMessage> {
+        let text = text.strip_prefix('<')?;
+        let (priority, rest) = text.split_once('>')?;
+        let priority: u8 = priority.parse().ok()?;
+        let facility = SyslogFacility::from_code(priority >> 3)?.0;
+        let severity = SyslogSeverity::from_code(priority & 7)?;
+        let mut message = match rest.strip_prefix("1 ") {
+            Some(rest) => parse_5424(rest)?,
+            None => parse_3164(rest),
+        };
+        message.facility = facility;
+        message.severity = severity;
+        Some(message)
+    }
+
+    /// facility user and severity emerg, for what can't be parsed
+    pub(crate) fn new(message: &str) -> Self {
+        SyslogMessage {
+            facility: Facility::LOG_USER,
+            severity: SyslogSeverity::Emerg,
+            timestamp: None,
+            hostname: None,
+            process: None,
+            pid: None,
+            msgid: None,
+            structured_data: None,
+            message: message.to_string(),
+        }
+    }
+}
+
+/// `-` is the nil value of RFC 5424
+fn nil_or(field: &str) -> Option<String> {
+    (field != "-").then(|| field.to_string())
+}
+
+/// after `<pri>1 `: TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA [MSG]
+fn parse_5424(text: &str) -> Option<SyslogMessage> {
+    let mut fields = text.splitn(5, ' ');
+    let (timestamp, hostname, process, pid) = (fields.next()?, fields.next()?, fields.next()?, fields.next()?);
+    let rest = fields.next()?;
+    let (msgid, rest) = rest.split_once(' ').unwrap_or((rest, ""));
+    let (structured_data, message) = split_structured_data(rest)?;
+    let mut parsed = SyslogMessage::new(message.strip_prefix('\u{feff}').unwrap_or(message));
+    parsed.timestamp = nil_or(timestamp);
+    parsed.hostname = nil_or(hostname);
+    parsed.process = nil_or(process);
+    parsed.pid = pid.parse().ok();
+    parsed.msgid = nil_or(msgid);
+    parsed.structured_data = nil_or(structured_data);
+    Some(parsed)
+}
+
+/// `-` or `[id name="value"]...`, then the message; `]` and `"` may be escaped by `\` in the values
+fn split_structured_data(text: &str) -> Option<(&str, &str)> {
+    if let Some(rest) = text.strip_prefix('-') {
+        return Some(("-", rest.strip_prefix(' ').unwrap_or(rest)));
+    }
+    let (mut in_value, mut escaped, mut depth) = (false, false, 0);
+    for (i, c) in text.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_value => escaped = true,
+            '"' => in_value = !in_value,
+            '[' if !in_value => depth += 1,
+            ']' if !in_value => depth -= 1,
+            ' ' if !in_value && depth == 0 => return Some((&text[..i], &text[i + 1..])),
+            _ => {}
+        }
+    }
+    (depth == 0).then_some((text, ""))
+}
+
+/// after `<pri>`: [Mmm dd hh:mm:ss ][HOSTNAME ][TAG[PID]: ]MSG
+fn parse_3164(text: &str) -> SyslogMessage {
+    let mut rest = text;
+    let mut timestamp = None;
+    // `get` as the message may have multi-byte chars anywhere
+    if let (Some(month), Some(stamp), Some(b' ')) = (rest.get(..3), rest.get(..15), rest.as_bytes().get(15)) {
+        if MONTHS.contains(&month) {
+            timestamp = Some(stamp.to_string());
+            rest = &rest[16..];
+        }
+    }
+    let is_tag = |token: &str| token.ends_with(':') && !token.contains('"');
+    let mut hostname = None;
+    let mut tag = None;
+    if let Some((first, after_first)) = rest.split_once(' ') {
+        if is_tag(first) {
+            tag = Some(first);
+            rest = after_first;
+        } else if let Some((second, after_second)) = after_first.split_once(' ') {
+            if is_tag(second) {
+                hostname = Some(first.to_string());
+                tag = Some(second);
+                rest = after_second;
+            }
+        }
+    }
+    let mut parsed = SyslogMessage::new(rest);
+    parsed.timestamp = timestamp;
+    parsed.hostname = hostname;
+    if let Some(tag) = tag.map(|tag| tag.trim_end_matches(':')) {
+        match tag.strip_suffix(']').and_then(|tag| tag.split_once('[')) {
+            Some((process, pid)) => {
+                parsed.process = Some(process.to_string());
+                parsed.pid = pid.parse().ok();
+            }
+            None => parsed.process = Some(tag.to_string()),
+        }
+    }
+    parsed
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+    use std::net::UdpSocket;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+
+    use flexi_logger::DeferredNow;
+    use log::{Log, Record};
+    use syslog::{Facility, Formatter3164};
+
+    use super::*;
+    use crate::config_for_flexi_logger::{detailed_format, json_format};
+    use crate::config_for_syslog::SyslogLogger;
+
+    /// what the env_logger builders write
+    #[derive(Clone, Default)]
+    struct Written(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Written {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn with_record(f: impl FnOnce(&Record)) {
+        let kvs: &[(&str, &dyn log::kv::ToValue)] = &[("user_id", &42), ("note", &"say \"hi\" ]"), ("ok", &true)];
+        f(&Record::builder()
+            .level(Level::Warn)
+            .target("portal::db")
+            .module_path(Some("portal::db"))
+            .file(Some("src/db.rs"))
+            .line(Some(12))
+            .key_values(&kvs)
+            .args(format_args!("query failed: {}", "timeout"))
+            .build());
+    }
+
+    fn with_env_logger(builder: env_logger::Builder) -> String {
+        let mut builder = builder;
+        let written = Written::default();
+        let logger = builder.target(env_logger::Target::Pipe(Box::new(written.clone()))).build();
+        with_record(|record| logger.log(record));
+        let line = String::from_utf8(written.0.lock().unwrap().clone()).unwrap();
+        line
+    }
+
+    fn assert_round_trip(parsed: &ParsedRecord, format: LineFormat) {
+        assert_eq!(parsed.format, format);
+        assert_eq!(parsed.level, Level::Warn);
+        assert_eq!(parsed.message, "query failed: timeout");
+        assert_eq!(parsed.key_values, vec![
+            ("user_id".to_string(), "42".to_string()),
+            ("note".to_string(), "say \"hi\" ]".to_string()),
+            ("ok".to_string(), "true".to_string()),
+        ]);
+        assert_eq!(parsed.host.as_deref(), Some(crate::hostname().as_str()));
+        let time = parsed.time.expect("time");
+        assert!((Local::now().fixed_offset() - time).num_seconds().abs() < 60, "{}", time);
+        if format != LineFormat::Syslog {
+            assert_eq!(parsed.ip.as_deref(), Some(crate::get_proper_ip().as_str()));
+            assert_eq!(parsed.module.as_deref(), Some("portal::db"));
+            assert_eq!(parsed.file.as_deref(), Some("src/db.rs"));
+            assert_eq!(parsed.line, Some(12));
+        }
+    }
+
+    #[test]
+    fn test_round_trip_text() {
+        let mut line = vec![];
+        with_record(|record| detailed_format(&mut line, &mut DeferredNow::new(), record).unwrap());
+        assert_round_trip(&parse_line(&String::from_utf8(line).unwrap()).unwrap(), LineFormat::Text);
+
+        // colored if the target is a terminal, never the case of a pipe
+        let line = with_env_logger(crate::get_default_env_logger_builder("debug"));
+        assert_round_trip(&parse_line(&line).unwrap(), LineFormat::Text);
+    }
+
+    #[test]
+    fn test_round_trip_json() {
+        let mut line = vec![];
+        with_record(|record| json_format(&mut line, &mut DeferredNow::new(), record).unwrap());
+        assert_round_trip(&parse_line(&String::from_utf8(line).unwrap()).unwrap(), LineFormat::Json);
+
+        let line = with_env_logger(crate::config_for_env_logger::get_json_env_logger_builder("debug"));
+        assert_round_trip(&parse_line(&line).unwrap(), LineFormat::Json);
+    }
+
+    #[test]
+    fn test_round_trip_syslog() {
+        let server = UdpSocket::bind("127.0.0.1:0").unwrap();
+        server.set_read_timeout(Some(Duration::from_secs(3))).unwrap();
+        let formatter = Formatter3164 {
+            facility: Facility::LOG_LOCAL1,
+            hostname: Some(crate::hostname() + "_" + &crate::get_proper_ip()),
+            process: "portal".to_string(),
+            pid: 42,
+        };
+        let logger = SyslogLogger::new(syslog::udp(formatter, "127.0.0.1:0".to_string(), server.local_addr().unwrap().to_string()).unwrap());
+        with_record(|record| logger.log(record));
+        let mut buf = [0; 2048];
+        let n = server.recv(&mut buf).unwrap();
+
+        let parsed = parse_line(std::str::from_utf8(&buf[..n]).unwrap()).unwrap();
+        assert_round_trip(&parsed, LineFormat::Syslog);
+        assert_eq!(parsed.ip.as_deref(), Some(crate::get_proper_ip().as_str()));
+        assert_eq!(parsed.facility, Some(SyslogFacility(Facility::LOG_LOCAL1)));
+        assert_eq!(parsed.severity, Some(SyslogSeverity::Warning));
+        assert_eq!((parsed.process.as_deref(), parsed.pid), (Some("portal"), Some(42)));
+    }
+
+    #[test]
+    fn test_parse_line() {
+        let parsed = parse_line("[WARN] 2021-11-14 11:51:24.123456 +08:00 portal-web-1-10.0.0.7 <unnamed> file:///src/db.rs:7 a=b slow ms=1200 sql=\"select 1\"").unwrap();
+        assert_eq!(parsed.time, DateTime::parse_from_rfc3339("2021-11-14T11:51:24.123456+08:00").ok());
+        assert_eq!((parsed.host.as_deref(), parsed.ip.as_deref()), (Some("portal-web-1"), Some("10.0.0.7")));
+        assert_eq!((parsed.module.as_deref(), parsed.file.as_deref(), parsed.line), (None, Some("/src/db.rs"), Some(7)));
+        assert_eq!(parsed.message, "a=b slow");
+        assert_eq!(parsed.key_value("sql"), Some("select 1"));
+        // the trailing key=value words of a message can't be told apart from the key-values
+        let parsed = parse_text("[INFO] 2021-11-14 11:51:24.123456 +08:00 portal-web-1-10.0.0.7 portal src/db.rs:9 connected host=db1").unwrap();
+        assert_eq!((parsed.message.as_str(), parsed.key_value("host")), ("connected", Some("db1")));
+
+        let parsed = parse_line("<133>1 2026-10-19T08:03:29Z web_10.0.0.7 portal 42 - [fblog@32473 user_id=\"42\"] joined\n").unwrap();
+        assert_eq!((parsed.level, parsed.severity), (Level::Info, Some(SyslogSeverity::Notice)));
+        assert_eq!(parsed.time, DateTime::parse_from_rfc3339("2026-10-19T08:03:29Z").ok());
+        assert_eq!((parsed.message.as_str(), parsed.key_value("user_id")), ("joined", Some("42")));
+
+        let parsed = parse_json(r#"{"level":"INFO","time":"2021-11-14 11:51:24.1234 +08:00:00","module":"","line":0,"msg":"a\tbé"}"#).unwrap();
+        assert_eq!(parsed.time, DateTime::parse_from_rfc3339("2021-11-14T11:51:24.1234+08:00").ok());
+        assert_eq!((parsed.module, parsed.line, parsed.message.as_str()), (None, None, "a\tbé"));
+
+        assert_eq!(parse_line("thread 'main' panicked at src/main.rs:3:5"), None);
+        assert_eq!(parse_line(r#"{"level":"INFO","msg":"x""#), None);
+        assert_eq!(parse_line(""), None);
+    }
+
+    #[test]
+    fn test_parse_syslog_message() {
+        let message = SyslogMessage::parse("<139>Oct  9 08:03:29 host_10.0.0.7N24 portal[42]: connection lost: timeout").unwrap();
+        assert!(matches!(message.facility, Facility::LOG_LOCAL1));
+        assert_eq!(message.severity_name(), "err");
+        assert_eq!(message.timestamp.as_deref(), Some("Oct  9 08:03:29"));
+        assert_eq!(message.hostname.as_deref(), Some("host_10.0.0.7N24"));
+        assert_eq!((message.process.as_deref(), message.pid), (Some("portal"), Some(42)));
+        assert_eq!(message.message, "connection lost: timeout");
+
+        let message = SyslogMessage::parse("<13>portal: started").unwrap();
+        assert_eq!((message.hostname, message.process.as_deref(), message.pid), (None, Some("portal"), None));
+        assert_eq!(message.message, "started");
+
+        let message = SyslogMessage::parse(r#"<14>1 2026-10-19T08:03:29Z host portal 42 joined [fblog@32473 user_id="42" room="a \"b\" ]"] user joined"#).unwrap();
+        assert!(matches!(message.facility, Facility::LOG_USER));
+        assert_eq!(message.severity_name(), "info");
+        assert_eq!(message.timestamp.as_deref(), Some("2026-10-19T08:03:29Z"));
+        assert_eq!((message.process.as_deref(), message.pid), (Some("portal"), Some(42)));
+        assert_eq!(message.msgid.as_deref(), Some("joined"));
+        assert_eq!(message.structured_data.as_deref(), Some(r#"[fblog@32473 user_id="42" room="a \"b\" ]"]"#));
+        assert_eq!(message.message, "user joined");
+
+        let message = SyslogMessage::parse("<15>1 - - - - - -").unwrap();
+        assert_eq!((message.timestamp, message.structured_data, message.message.as_str()), (None, None, ""));
+
+        // multi-byte chars where the timestamp would be
+        assert_eq!(SyslogMessage::parse("<13>ab€defghijklmnopq").unwrap().message, "ab€defghijklmnopq");
+        assert_eq!(SyslogMessage::parse("<13>Oct 19 08:03:2€ portal: x").unwrap().timestamp, None);
+        assert_eq!(SyslogMessage::parse("<13>Oct 19 08:03:29 主机 portal: 连接").unwrap().message, "连接");
+
+        assert!(SyslogMessage::parse("no priority").is_none());
+        assert!(SyslogMessage::parse("<200>Oct  9 08:03:29 x").is_none());
+    }
+}
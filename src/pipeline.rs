@@ -0,0 +1,343 @@
+use std::cell::Cell;
+use std::sync::{Arc, Mutex, RwLock};
+
+use log::{LevelFilter, Log, Metadata, Record, SetLoggerError};
+use once_cell::sync::Lazy;
+
+use crate::dedup::Deduplicator;
+use crate::rate_limit::RateLimiter;
+use crate::redact::Redactor;
+use crate::ring_buffer::RingBuffer;
+#[cfg(any(test, feature = "test-helpers"))]
+use crate::test_helper::{capture_flush, capture_record, is_capturing};
+
+/// without the test helpers, nothing is captured
+#[cfg(not(any(test, feature = "test-helpers")))]
+fn is_capturing() -> bool {
+    false
+}
+
+#[cfg(not(any(test, feature = "test-helpers")))]
+fn capture_record(_record: &Record) {}
+
+#[cfg(not(any(test, feature = "test-helpers")))]
+fn capture_flush() {}
+
+static G_STAGES: Lazy<RwLock<Stages>> = Lazy::new(RwLock::default);
+/// the logger behind the pipeline, replaced by each start function
+static G_SINK: Lazy<RwLock<Option<Arc<Sink>>>> = Lazy::new(RwLock::default);
+
+thread_local! {
+    /// true while the records of the ring buffer are written to the sink
+    static REPLAYING: Cell<bool> = const { Cell::new(false) };
+}
+
+/// Writes a buffered record to the sink whatever its level, see `Sink::replay`.
+pub(crate) type ReplayWriter = Box<dyn Fn(&Record) + Send + Sync>;
+
+/// The logger of a start function, and how to write to it the records it is not enabled for.
+struct Sink {
+    logger: Box<dyn Log>,
+    /// None: `logger.log`, which the sinks skip their level filter in while `is_replaying()`
+    replay: Option<ReplayWriter>,
+}
+
+impl Sink {
+    /// write a record of the ring buffer, at its own level, bypassing the level filters of the sink
+    fn replay(&self, record: &Record) {
+        REPLAYING.with(|replaying| replaying.set(true));
+        match &self.replay {
+            Some(replay) => replay(record),
+            None => self.logger.log(record),
+        }
+        REPLAYING.with(|replaying| replaying.set(false));
+    }
+}
+
+/// true while a record of the ring buffer is written, the sinks must then write it whatever their spec
+pub(crate) fn is_replaying() -> bool {
+    REPLAYING.with(Cell::get)
+}
+
+/// A step every record goes through before reaching any sink.
+pub(crate) trait Stage: Send + Sync {
+    /// pass `record` on by calling `next`, zero or more times, possibly with other records (eg: summaries)
+    fn process(&self, record: &Record, next: &mut dyn FnMut(&Record));
+
+    /// called when the logger is flushed, to pass on what the stage holds back
+    fn flush(&self, _next: &mut dyn FnMut(&Record)) {}
+}
+
+/// The optional stages, applied in the order of the fields.
+#[derive(Default)]
+struct Stages {
+    rate_limiter: Option<Arc<RateLimiter>>,
+    redactor: Option<Arc<Redactor>>,
+    /// keeps what the sinks are not enabled for, before the deduplicator so that it only sees what is logged
+    ring_buffer: Option<Arc<RingBuffer>>,
+    deduplicator: Option<Arc<Deduplicator>>,
+    /// the max level of the started logger, None before it is started
+    sinks_max_level: Option<LevelFilter>,
+    /// the active `test_helper::capture()`s, of all threads
+    captures: usize,
+    /// built from the fields above whenever they change
+    chain: Arc<Chain>,
+}
+
+/// What a record goes through, shared by all log calls until a stage is set: logging only clones the Arc.
+#[derive(Default)]
+struct Chain {
+    stages: Vec<Arc<dyn Stage>>,
+    /// the records reaching `stages[buffer_at]` (or the sink) that the sink is not enabled for go to the ring buffer
+    buffer_at: usize,
+    ring_buffer: Option<Arc<RingBuffer>>,
+}
+
+impl Stages {
+    fn build_chain(&self) -> Chain {
+        let mut stages: Vec<Arc<dyn Stage>> = vec![];
+        if let Some(rate_limiter) = &self.rate_limiter {
+            stages.push(rate_limiter.clone());
+        }
+        if let Some(redactor) = &self.redactor {
+            stages.push(redactor.clone());
+        }
+        let buffer_at = stages.len();
+        if let Some(deduplicator) = &self.deduplicator {
+            stages.push(deduplicator.clone());
+        }
+        Chain { stages, buffer_at, ring_buffer: self.ring_buffer.clone() }
+    }
+
+    fn apply_max_level(&self) {
+        if self.ring_buffer.is_some() || self.captures > 0 {
+            log::set_max_level(LevelFilter::Trace);
+        } else if let Some(sinks_max_level) = self.sinks_max_level {
+            log::set_max_level(sinks_max_level);
+        }
+    }
+}
+
+/// Apply `rate_limiter` to all records before any sink, None to disable it.
+/// <br>
+/// Can be called before or after starting the logger.
+pub fn set_rate_limiter(rate_limiter: Option<RateLimiter>) {
+    stages_mut(|stages| stages.rate_limiter = rate_limiter.map(Arc::new));
+}
+
+/// Mask secrets and personal data with `redactor`, None to disable it.
+/// <br>
+/// Can be called before or after starting the logger.
+pub fn set_redactor(redactor: Option<Redactor>) {
+    stages_mut(|stages| stages.redactor = redactor.map(Arc::new));
+}
+
+/// Collapse consecutive identical records with `deduplicator`, None to disable it.
+/// <br>
+/// Can be called before or after starting the logger.
+pub fn set_deduplicator(deduplicator: Option<Deduplicator>) {
+    stages_mut(|stages| stages.deduplicator = deduplicator.map(Arc::new));
+}
+
+/// Keep the records the sinks are not enabled for in `ring_buffer`, None to disable it, see `RingBuffer`.
+/// <br>
+/// Can be called before or after starting the logger.
+pub fn set_ring_buffer(ring_buffer: Option<RingBuffer>) {
+    stages_mut(|stages| {
+        stages.ring_buffer = ring_buffer.map(Arc::new);
+        stages.apply_max_level();
+    });
+}
+
+/// Log the records kept by the ring buffer (if any) at their own level, returns how many were logged.
+pub(crate) fn dump_ring_buffer() -> usize {
+    match (chain().ring_buffer.as_ref(), sink()) {
+        (Some(ring_buffer), Some(sink)) => ring_buffer.dump(&mut |record| sink.replay(record)),
+        _ => 0,
+    }
+}
+
+/// Called by the start functions instead of `log::set_max_level`: `max_level` is the one of the sinks,
+/// `log::max_level()` stays at trace while a ring buffer is set.
+pub(crate) fn set_max_level(max_level: LevelFilter) {
+    stages_mut(|stages| {
+        stages.sinks_max_level = Some(max_level);
+        stages.apply_max_level();
+    });
+}
+
+/// called by `test_helper::capture()`, keeps `log::max_level()` at trace while a capture is active
+#[cfg(any(test, feature = "test-helpers"))]
+pub(crate) fn add_capture() {
+    stages_mut(|stages| {
+        stages.captures += 1;
+        stages.apply_max_level();
+    });
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+pub(crate) fn remove_capture() {
+    stages_mut(|stages| {
+        stages.captures = stages.captures.saturating_sub(1);
+        stages.apply_max_level();
+    });
+}
+
+fn stages_mut(f: impl FnOnce(&mut Stages)) {
+    let mut stages = G_STAGES.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+    f(&mut stages);
+    stages.chain = Arc::new(stages.build_chain());
+}
+
+fn chain() -> Arc<Chain> {
+    G_STAGES.read().unwrap_or_else(|poisoned| poisoned.into_inner()).chain.clone()
+}
+
+/// pass `record` through `chain.stages[from..]` to `sink`
+fn run(chain: &Chain, from: usize, record: &Record, sink: &Sink) {
+    let capturing = is_capturing();
+    if from == chain.buffer_at && (chain.ring_buffer.is_some() || capturing) && !sink.logger.enabled(record.metadata()) {
+        if capturing {
+            capture_record(record);
+        }
+        if let Some(ring_buffer) = &chain.ring_buffer {
+            ring_buffer.keep(record);
+        }
+        return;
+    }
+    match chain.stages.get(from) {
+        Some(stage) => stage.process(record, &mut |record| run(chain, from + 1, record, sink)),
+        None => deliver(record, sink, chain.ring_buffer.as_deref()),
+    }
+}
+
+/// the end of the chain: log `record`, after the ring buffer if it dumps on its level
+fn deliver(record: &Record, sink: &Sink, ring_buffer: Option<&RingBuffer>) {
+    if let Some(ring_buffer) = ring_buffer.filter(|ring_buffer| ring_buffer.dumps_on(record.level())) {
+        ring_buffer.dump(&mut |buffered| sink.replay(buffered));
+    }
+    capture_record(record);
+    sink.logger.log(record);
+}
+
+/// The global logger, installed once by the first start function: passes every record through the stages,
+/// then to the logger of the last start function.
+struct Pipeline;
+
+static G_PIPELINE: Pipeline = Pipeline;
+/// true once `G_PIPELINE` is the global logger
+static G_INSTALLED: Mutex<bool> = Mutex::new(false);
+
+fn sink() -> Option<Arc<Sink>> {
+    G_SINK.read().unwrap_or_else(|poisoned| poisoned.into_inner()).clone()
+}
+
+impl Log for Pipeline {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        chain().ring_buffer.is_some() || is_capturing() || sink().is_some_and(|sink| sink.logger.enabled(metadata))
+    }
+
+    fn log(&self, record: &Record) {
+        let Some(sink) = sink() else {
+            return;
+        };
+        let chain = chain();
+        if chain.ring_buffer.is_none() && !is_capturing() && !sink.logger.enabled(record.metadata()) {
+            return;
+        }
+        run(&chain, 0, record, &sink);
+    }
+
+    fn flush(&self) {
+        let Some(sink) = sink() else {
+            return;
+        };
+        let chain = chain();
+        for (i, stage) in chain.stages.iter().enumerate() {
+            stage.flush(&mut |record| run(&chain, i + 1, record, &sink));
+        }
+        capture_flush();
+        sink.logger.flush();
+    }
+}
+
+/// Like `log::set_boxed_logger`, with `inner` behind the pipeline.
+/// <br>
+/// The pipeline is set as the global logger on the first call only, later calls replace the logger behind it
+/// (the previous one is flushed here, and shut down by `control::ControlHandle::register`), so the start functions can be called more than once in a process, eg: by each test.
+/// Fails only if another logger was set by `log::set_logger`.
+pub(crate) fn set_boxed_logger(inner: Box<dyn Log>) -> Result<(), SetLoggerError> {
+    set_boxed_logger_with_replay(inner, None)
+}
+
+/// same as `set_boxed_logger`, `replay` writes the records of the ring buffer straight to the writers of `inner`,
+/// for loggers that check their level before `log` reaches their writers, eg: flexi_logger
+pub(crate) fn set_boxed_logger_with_replay(inner: Box<dyn Log>, replay: Option<ReplayWriter>) -> Result<(), SetLoggerError> {
+    {
+        let mut installed = G_INSTALLED.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        if !*installed {
+            log::set_logger(&G_PIPELINE)?;
+            *installed = true;
+        }
+    }
+    let sink = Sink { logger: inner, replay };
+    let previous = G_SINK.write().unwrap_or_else(|poisoned| poisoned.into_inner()).replace(Arc::new(sink));
+    if let Some(previous) = previous {
+        previous.logger.flush();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+
+    use super::*;
+
+    /// enabled for info and above
+    struct CaptureLog(Mutex<Vec<String>>);
+
+    impl Log for CaptureLog {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= Level::Info
+        }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push(format!("{} {}", record.level(), record.args()));
+        }
+
+        fn flush(&self) {}
+    }
+
+    /// the records of `messages` through a ring buffer and a deduplicator to a sink at info, returns what it logged
+    fn log_through_chain(messages: &[(Level, &str)]) -> Vec<String> {
+        let logged = Arc::new(CaptureLog(Mutex::new(vec![])));
+        let sink = Sink { logger: Box::new(Arc::clone(&logged)), replay: None };
+        let stages = Stages {
+            ring_buffer: Some(Arc::new(RingBuffer::new(10))),
+            deduplicator: Some(Arc::new(Deduplicator::new(std::time::Duration::from_secs(30)))),
+            ..Stages::default()
+        };
+        let chain = stages.build_chain();
+        for (level, message) in messages {
+            run(&chain, 0, &Record::builder().level(*level).args(format_args!("{}", message)).build(), &sink);
+        }
+        let logged = logged.0.lock().unwrap().clone();
+        // without the time, eg: "DEBUG [buffered 10:02:03.514] connecting" -> "DEBUG [buffered] connecting"
+        logged.iter()
+            .map(|line| match line.split_once("[buffered ") {
+                Some((head, rest)) => format!("{}[buffered]{}", head, &rest[rest.find(']').unwrap() + 1..]),
+                None => line.clone(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_ring_buffer_in_chain() {
+        let logged = log_through_chain(&[(Level::Debug, "connecting"), (Level::Info, "started"), (Level::Trace, "query"),
+                                         (Level::Trace, "query"), (Level::Error, "connection lost"), (Level::Warn, "retrying")]);
+        // replayed at their own level, the repeated trace records are kept as is rather than deduplicated
+        assert_eq!(logged, vec!["INFO started", "DEBUG [buffered] connecting", "TRACE [buffered] query", "TRACE [buffered] query",
+                                "ERROR connection lost", "WARN retrying"]);
+    }
+}
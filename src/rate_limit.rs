@@ -0,0 +1,272 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use log::{Level, Record};
+
+use crate::pipeline::Stage;
+use crate::syslog_codes::covers;
+
+/// What the records of a single call site (file:line) may send.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Limits {
+    /// burst, per second
+    rate: Option<(u32, f64)>,
+    sample_one_in: Option<u32>,
+}
+
+impl Limits {
+    pub fn unlimited() -> Self {
+        Self::default()
+    }
+
+    /// a token bucket: at most `burst` records at once, refilled with `per_second` records per second
+    pub fn rate(burst: u32, per_second: f64) -> Self {
+        Self { rate: Some((burst, per_second)), sample_one_in: None }
+    }
+
+    /// keep 1 in `n` debug/trace records, before the token bucket
+    pub fn sample_debug(mut self, one_in: u32) -> Self {
+        self.sample_one_in = Some(one_in.max(1));
+        self
+    }
+}
+
+/// Limits the records of every call site, so that eg: a warning in a hot loop can't flood rsyslog.
+/// <br>
+/// Suppressed records are counted, and summarized as `suppressed N similar messages` (with the level,
+/// target, file and line of the call site) at most every `summary_interval`, with the next record or on flush.
+/// <br>
+/// eg: 10 records at once then 1 per second for every call site, except for the `h2` module:
+/// ```ignore
+/// fblog::pipeline::set_rate_limiter(Some(RateLimiter::new(Limits::rate(10, 1.0))
+///     .with_module("h2", Limits::unlimited())));
+/// ```
+pub struct RateLimiter {
+    default: Limits,
+    /// module (target prefix), limits
+    modules: Vec<(String, Limits)>,
+    summary_interval: Duration,
+    state: Mutex<State>,
+}
+
+struct State {
+    sites: HashMap<u64, Site>,
+    last_summary: Option<Instant>,
+}
+
+struct Site {
+    tokens: f64,
+    last_refill: Instant,
+    /// debug/trace records to drop before the next one passes
+    sampled: u64,
+    suppressed: u64,
+    level: Level,
+    target: String,
+    file: Option<String>,
+    line: Option<u32>,
+}
+
+impl RateLimiter {
+    pub fn new(default: Limits) -> Self {
+        Self {
+            default,
+            modules: vec![],
+            summary_interval: Duration::from_secs(10),
+            state: Mutex::new(State { sites: HashMap::new(), last_summary: None }),
+        }
+    }
+
+    /// use `limits` for the records whose target starts with `module`, the longest module wins
+    pub fn with_module(mut self, module: &str, limits: Limits) -> Self {
+        self.modules.push((module.to_string(), limits));
+        self
+    }
+
+    /// default: 10 seconds
+    pub fn with_summary_interval(mut self, summary_interval: Duration) -> Self {
+        self.summary_interval = summary_interval;
+        self
+    }
+
+    fn limits_for(&self, target: &str) -> Limits {
+        self.modules
+            .iter()
+            .filter(|(module, _)| covers(module, target))
+            .max_by_key(|(module, _)| module.len())
+            .map(|(_, limits)| *limits)
+            .unwrap_or(self.default)
+    }
+
+    fn process_at(&self, record: &Record, now: Instant, next: &mut dyn FnMut(&Record)) {
+        let limits = self.limits_for(record.target());
+        let (pass, summaries) = {
+            let mut state = self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+            let pass = limits == Limits::unlimited() || Self::take(&mut state, &limits, record, now);
+            let summaries = match state.last_summary {
+                Some(last_summary) if now.duration_since(last_summary) < self.summary_interval => vec![],
+                _ => {
+                    state.last_summary = Some(now);
+                    Self::take_summaries(&mut state)
+                }
+            };
+            (pass, summaries)
+        };
+        // the lock is released, a sink may log itself
+        for summary in &summaries {
+            summary.emit(next);
+        }
+        if pass {
+            next(record);
+        }
+    }
+
+    /// true if `record` may pass
+    fn take(state: &mut State, limits: &Limits, record: &Record, now: Instant) -> bool {
+        let site = state.sites.entry(call_site(record)).or_insert_with(|| Site {
+            tokens: limits.rate.map(|(burst, _)| burst as f64).unwrap_or_default(),
+            last_refill: now,
+            sampled: 0,
+            suppressed: 0,
+            level: record.level(),
+            target: record.target().to_string(),
+            file: record.file().map(str::to_string),
+            line: record.line(),
+        });
+        if let (Some(one_in), Level::Debug | Level::Trace) = (limits.sample_one_in, record.level()) {
+            // the first record passes, the next `one_in - 1` are dropped
+            if site.sampled > 0 {
+                site.sampled -= 1;
+                return false;
+            }
+            site.sampled = one_in as u64 - 1;
+        }
+        if let Some((burst, per_second)) = limits.rate {
+            let elapsed = now.saturating_duration_since(site.last_refill).as_secs_f64();
+            site.tokens = (site.tokens + elapsed * per_second).min(burst as f64);
+            site.last_refill = now;
+            if site.tokens < 1.0 {
+                site.suppressed += 1;
+                return false;
+            }
+            site.tokens -= 1.0;
+        }
+        true
+    }
+
+    fn take_summaries(state: &mut State) -> Vec<Summary> {
+        state.sites
+            .values_mut()
+            .filter(|site| site.suppressed > 0)
+            .map(|site| Summary {
+                suppressed: std::mem::take(&mut site.suppressed),
+                level: site.level,
+                target: site.target.clone(),
+                file: site.file.clone(),
+                line: site.line,
+            })
+            .collect()
+    }
+}
+
+impl Stage for RateLimiter {
+    fn process(&self, record: &Record, next: &mut dyn FnMut(&Record)) {
+        self.process_at(record, Instant::now(), next);
+    }
+
+    fn flush(&self, next: &mut dyn FnMut(&Record)) {
+        let summaries = Self::take_summaries(&mut self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        for summary in &summaries {
+            summary.emit(next);
+        }
+    }
+}
+
+struct Summary {
+    suppressed: u64,
+    level: Level,
+    target: String,
+    file: Option<String>,
+    line: Option<u32>,
+}
+
+impl Summary {
+    fn emit(&self, next: &mut dyn FnMut(&Record)) {
+        next(&Record::builder()
+            .level(self.level)
+            .target(&self.target)
+            .file(self.file.as_deref())
+            .line(self.line)
+            .args(format_args!("suppressed {} similar messages", self.suppressed))
+            .build());
+    }
+}
+
+/// records of the same call site have the same target, file and line
+fn call_site(record: &Record) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    (record.target(), record.file(), record.line()).hash(&mut hasher);
+    hasher.finish()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn send(limiter: &RateLimiter, level: Level, target: &str, line: u32, now: Instant, passed: &mut Vec<String>) {
+        limiter.process_at(&Record::builder()
+            .level(level)
+            .target(target)
+            .file(Some("src/job.rs"))
+            .line(Some(line))
+            .args(format_args!("slow"))
+            .build(), now, &mut |record| passed.push(format!("{}:{} {}", record.target(), record.line().unwrap(), record.args())));
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limiter = RateLimiter::new(Limits::rate(2, 1.0))
+            .with_module("portal::h2", Limits::unlimited())
+            .with_summary_interval(Duration::from_secs(1));
+        let start = Instant::now();
+        let mut passed = vec![];
+        for _ in 0..5 {
+            send(&limiter, Level::Warn, "portal", 42, start, &mut passed);
+            send(&limiter, Level::Warn, "portal::h2::conn", 7, start, &mut passed);
+        }
+        // another call site has its own bucket
+        send(&limiter, Level::Warn, "portal", 43, start, &mut passed);
+        assert_eq!(passed.iter().filter(|line| line.as_str() == "portal:42 slow").count(), 2);
+        assert_eq!(passed.iter().filter(|line| line.as_str() == "portal::h2::conn:7 slow").count(), 5);
+        assert_eq!(passed.iter().filter(|line| line.as_str() == "portal:43 slow").count(), 1);
+
+        passed.clear();
+        send(&limiter, Level::Warn, "portal", 42, start + Duration::from_millis(1500), &mut passed);
+        assert_eq!(passed, vec!["portal:42 suppressed 3 similar messages", "portal:42 slow"]);
+
+        passed.clear();
+        send(&limiter, Level::Warn, "portal", 42, start + Duration::from_millis(1600), &mut passed);
+        limiter.flush(&mut |record| passed.push(format!("{}:{} {}", record.target(), record.line().unwrap(), record.args())));
+        assert_eq!(passed, vec!["portal:42 suppressed 1 similar messages"]);
+    }
+
+    #[test]
+    fn test_sample_debug() {
+        let limiter = RateLimiter::new(Limits::unlimited().sample_debug(3))
+            .with_module("portal::db", Limits::unlimited());
+        let now = Instant::now();
+        let mut passed = vec![];
+        for _ in 0..6 {
+            send(&limiter, Level::Debug, "portal", 1, now, &mut passed);
+            send(&limiter, Level::Info, "portal", 2, now, &mut passed);
+            send(&limiter, Level::Debug, "portal::db", 3, now, &mut passed);
+            send(&limiter, Level::Debug, "portal::dbx", 4, now, &mut passed);
+        }
+        assert_eq!(passed.iter().filter(|line| line.starts_with("portal:1 ")).count(), 2);
+        assert_eq!(passed.iter().filter(|line| line.starts_with("portal:2 ")).count(), 6);
+        assert_eq!(passed.iter().filter(|line| line.starts_with("portal::db:3 ")).count(), 6);
+        assert_eq!(passed.iter().filter(|line| line.starts_with("portal::dbx:4 ")).count(), 2);
+    }
+}
@@ -0,0 +1,193 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use chrono::{DateTime, Local};
+use log::{Level, Record};
+
+use crate::key_values::{collect, OwnedPairs};
+
+/// Keeps in memory the last records the sinks are not enabled for (eg: debug and trace while the sinks are at info),
+/// and logs them when an error is logged, so failures come with their debug context, eg:
+/// ```ignore
+/// fblog::pipeline::set_ring_buffer(Some(RingBuffer::new(10_000).with_max_bytes(4 << 20)));
+/// ```
+/// The buffered records are logged before the record that triggered the dump, at their own level
+/// and whatever the spec of the sinks, with their time as a prefix of the message:
+/// <br>
+/// ```[DEBUG] ... src/db.rs:12 [buffered 10:02:03.514] connecting to 10.0.0.7:5432```
+/// <br>
+/// The sinks of env_logger can't be bypassed: they only write the buffered records their filters are enabled for.
+/// <br>
+/// While a ring buffer is set, `log::max_level()` stays at trace, so every record reaches it.
+pub struct RingBuffer {
+    max_records: usize,
+    max_bytes: Option<usize>,
+    dump_on: Option<Level>,
+    state: Mutex<Buffer>,
+}
+
+#[derive(Default)]
+struct Buffer {
+    records: VecDeque<Buffered>,
+    bytes: usize,
+}
+
+struct Buffered {
+    time: SystemTime,
+    level: Level,
+    target: String,
+    module_path: Option<String>,
+    file: Option<String>,
+    line: Option<u32>,
+    message: String,
+    fields: Vec<(String, String)>,
+}
+
+impl Buffered {
+    /// roughly the memory it holds
+    fn size(&self) -> usize {
+        std::mem::size_of::<Self>()
+            + self.target.len()
+            + self.module_path.as_ref().map_or(0, String::len)
+            + self.file.as_ref().map_or(0, String::len)
+            + self.message.len()
+            + self.fields.iter().map(|(key, value)| key.len() + value.len()).sum::<usize>()
+    }
+
+    fn emit(&self, next: &mut dyn FnMut(&Record)) {
+        let kvs = OwnedPairs(&self.fields);
+        next(&Record::builder()
+            .level(self.level)
+            .target(&self.target)
+            .module_path(self.module_path.as_deref())
+            .file(self.file.as_deref())
+            .line(self.line)
+            .key_values(&kvs)
+            .args(format_args!("[buffered {}] {}", DateTime::<Local>::from(self.time).format("%H:%M:%S%.3f"), self.message))
+            .build());
+    }
+}
+
+impl RingBuffer {
+    /// keep the last `max_records` records
+    pub fn new(max_records: usize) -> Self {
+        Self {
+            max_records,
+            max_bytes: None,
+            dump_on: Some(Level::Error),
+            state: Mutex::default(),
+        }
+    }
+
+    /// also keep at most about `max_bytes` of records
+    pub fn with_max_bytes(mut self, max_bytes: usize) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// dump when a record at `level` or above is logged, None to dump only through `ControlHandle::dump_ring_buffer`.
+    /// <br>
+    /// default: error
+    pub fn with_dump_on(mut self, level: Option<Level>) -> Self {
+        self.dump_on = level;
+        self
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, Buffer> {
+        self.state.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// keep `record`, which the sinks are not enabled for
+    pub(crate) fn keep(&self, record: &Record) {
+        let buffered = Buffered {
+            time: SystemTime::now(),
+            level: record.level(),
+            target: record.target().to_string(),
+            module_path: record.module_path().map(str::to_string),
+            file: record.file().map(str::to_string),
+            line: record.line(),
+            message: record.args().to_string(),
+            fields: collect(record.key_values()),
+        };
+        let mut state = self.state();
+        state.bytes += buffered.size();
+        state.records.push_back(buffered);
+        while state.records.len() > self.max_records || self.max_bytes.is_some_and(|max_bytes| state.bytes > max_bytes) {
+            match state.records.pop_front() {
+                Some(dropped) => state.bytes -= dropped.size(),
+                None => break,
+            }
+        }
+    }
+
+    /// true if logging a record at `level` dumps the buffer first
+    pub(crate) fn dumps_on(&self, level: Level) -> bool {
+        self.dump_on.is_some_and(|dump_on| level <= dump_on)
+    }
+
+    /// pass the buffered records (oldest first, at their own level) to `next` and empty the buffer,
+    /// returns how many were passed
+    pub(crate) fn dump(&self, next: &mut dyn FnMut(&Record)) -> usize {
+        let records = std::mem::take(&mut *self.state()).records;
+        // the lock is released, a sink may log itself
+        for buffered in &records {
+            buffered.emit(next);
+        }
+        records.len()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::key_values::AsText;
+
+    fn keep(ring_buffer: &RingBuffer, level: Level, message: &str) {
+        let kvs: &[(&str, &str)] = &[("conn", "7")];
+        ring_buffer.keep(&Record::builder()
+            .level(level)
+            .target("portal::db")
+            .file(Some("src/db.rs"))
+            .line(Some(12))
+            .key_values(&kvs)
+            .args(format_args!("{}", message))
+            .build());
+    }
+
+    fn dump(ring_buffer: &RingBuffer) -> Vec<String> {
+        let mut dumped = vec![];
+        ring_buffer.dump(&mut |record| {
+            let message = record.args().to_string();
+            // without the time, eg: "[buffered 10:02:03.514] query 3" -> "[buffered] query 3"
+            let (head, rest) = message.split_once("] ").unwrap();
+            let head = &head[..head.rfind(' ').unwrap()];
+            dumped.push(format!("{} {} {}] {}{}", record.level(), record.target(), head, rest, AsText(record.key_values())));
+        });
+        dumped
+    }
+
+    #[test]
+    fn test_ring_buffer() {
+        let ring_buffer = RingBuffer::new(3);
+        assert!(ring_buffer.dumps_on(Level::Error));
+        assert!(!ring_buffer.dumps_on(Level::Warn));
+        for i in 0..5 {
+            keep(&ring_buffer, Level::Trace, &format!("query {}", i));
+        }
+        keep(&ring_buffer, Level::Debug, "connected");
+        assert_eq!(dump(&ring_buffer), vec![
+            "TRACE portal::db [buffered] query 3 conn=7",
+            "TRACE portal::db [buffered] query 4 conn=7",
+            "DEBUG portal::db [buffered] connected conn=7",
+        ]);
+        assert_eq!(dump(&ring_buffer), Vec::<String>::new());
+
+        let ring_buffer = RingBuffer::new(100).with_max_bytes(2 * std::mem::size_of::<Buffered>()).with_dump_on(None);
+        assert!(!ring_buffer.dumps_on(Level::Error));
+        for i in 0..5 {
+            keep(&ring_buffer, Level::Debug, &format!("query {}", i));
+        }
+        assert_eq!(dump(&ring_buffer), vec!["DEBUG portal::db [buffered] query 4 conn=7"]);
+    }
+}
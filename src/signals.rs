@@ -0,0 +1,82 @@
+use std::thread::JoinHandle;
+
+use log::*;
+use signal_hook::consts::{SIGHUP, SIGUSR1, SIGUSR2};
+use signal_hook::iterator::Signals;
+
+use crate::control::ControlHandle;
+
+/// Start a thread driving `control::handle()` by signals, after the logger was started:
+/// * `kill -USR1 <pid>`: raise every level of the spec one notch, eg: "info,h2=warn" -> "debug,h2=info"
+/// * `kill -USR2 <pid>`: go back to the spec(s) the logger was started with
+/// * `kill -HUP <pid>`: reopen the log files, eg: in the postrotate script of logrotate
+///
+/// The thread runs until the process exits.
+pub fn start_signal_handler() -> std::io::Result<JoinHandle<()>> {
+    start_signal_handler_for(crate::control::handle())
+}
+
+fn start_signal_handler_for(handle: &'static ControlHandle) -> std::io::Result<JoinHandle<()>> {
+    let mut signals = Signals::new([SIGUSR1, SIGUSR2, SIGHUP])?;
+    std::thread::Builder::new()
+        .name("fblog-signals".to_string())
+        .spawn(move || {
+            for signal in signals.forever() {
+                handle_signal(handle, signal);
+            }
+        })
+}
+
+fn handle_signal(handle: &ControlHandle, signal: i32) {
+    match signal {
+        SIGUSR1 => match handle.increase_verbosity() {
+            Ok(spec) => warn!("SIGUSR1: log spec raised to: {}", spec),
+            Err(err) => error!("SIGUSR1: could not raise log spec, err: {}", err),
+        },
+        SIGUSR2 => match handle.reset_spec() {
+            Ok(()) => warn!("SIGUSR2: log spec reset to: {}", handle.current_spec().unwrap_or_default()),
+            Err(err) => error!("SIGUSR2: could not reset log spec, err: {}", err),
+        },
+        SIGHUP => match handle.reopen_files() {
+            Ok(()) => info!("SIGHUP: log files reopened"),
+            Err(err) => error!("SIGHUP: could not reopen log files, err: {}", err),
+        },
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    /// the spec of `handle` once it is `expected`, or the last one after a few seconds
+    fn wait_for_spec(handle: &ControlHandle, expected: &str) -> Option<String> {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        loop {
+            let spec = handle.current_spec();
+            if spec.as_deref() == Some(expected) || Instant::now() >= deadline {
+                return spec;
+            }
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    #[test]
+    fn test_handle_signal() {
+        // the signals are raised for the whole process, the handler logs through the global logger
+        let _lock = crate::test_helper::logger_lock();
+        let handle: &'static ControlHandle = Box::leak(Box::new(ControlHandle::new()));
+        handle_signal(handle, SIGUSR1);
+        assert_eq!(handle.current_spec(), None);
+
+        crate::control::test::register_recording_backend(handle, "info");
+        start_signal_handler_for(handle).unwrap();
+        signal_hook::low_level::raise(SIGUSR1).unwrap();
+        assert_eq!(wait_for_spec(handle, "debug").as_deref(), Some("debug"));
+        signal_hook::low_level::raise(SIGHUP).unwrap();
+        signal_hook::low_level::raise(SIGUSR2).unwrap();
+        assert_eq!(wait_for_spec(handle, "info").as_deref(), Some("info"));
+    }
+}
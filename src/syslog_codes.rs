@@ -0,0 +1,359 @@
+use std::error::Error;
+use std::fmt;
+use std::str::FromStr;
+
+use log::Level;
+use syslog::Facility;
+
+/// (facility, name) of the facilities, their code is the one in the priority of a message (`facility as u8 >> 3`)
+const FACILITIES: [(Facility, &str); 20] = [
+    (Facility::LOG_KERN, "kern"), (Facility::LOG_USER, "user"), (Facility::LOG_MAIL, "mail"), (Facility::LOG_DAEMON, "daemon"),
+    (Facility::LOG_AUTH, "auth"), (Facility::LOG_SYSLOG, "syslog"), (Facility::LOG_LPR, "lpr"), (Facility::LOG_NEWS, "news"),
+    (Facility::LOG_UUCP, "uucp"), (Facility::LOG_CRON, "cron"), (Facility::LOG_AUTHPRIV, "authpriv"), (Facility::LOG_FTP, "ftp"),
+    (Facility::LOG_LOCAL0, "local0"), (Facility::LOG_LOCAL1, "local1"), (Facility::LOG_LOCAL2, "local2"), (Facility::LOG_LOCAL3, "local3"),
+    (Facility::LOG_LOCAL4, "local4"), (Facility::LOG_LOCAL5, "local5"), (Facility::LOG_LOCAL6, "local6"), (Facility::LOG_LOCAL7, "local7"),
+];
+
+/// A name or code that is not a syslog facility or severity.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseNameError {
+    /// "facility" or "severity"
+    kind: &'static str,
+    value: String,
+}
+
+impl fmt::Display for ParseNameError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "unknown syslog {}: {:?}", self.kind, self.value)
+    }
+}
+
+impl Error for ParseNameError {}
+
+/// A `syslog::Facility` with a name, parsed from config strings, eg:
+/// `"local1".parse::<SyslogFacility>()?.into()` -> `Facility::LOG_LOCAL1`
+/// <br>
+/// Names are case insensitive, with or without the `LOG_` prefix, codes are the ones of rsyslog, eg: `"17"` for local1.
+/// Displayed as the name rsyslog uses in its config, eg: `local1`.
+#[derive(Debug, Clone, Copy)]
+pub struct SyslogFacility(pub Facility);
+
+impl SyslogFacility {
+    pub fn from_code(code: u8) -> Option<Self> {
+        FACILITIES.iter().map(|(facility, _)| SyslogFacility(*facility)).find(|facility| facility.code() == code)
+    }
+
+    pub fn code(&self) -> u8 {
+        self.0 as u8 >> 3
+    }
+
+    /// eg: "local1"
+    pub fn name(&self) -> &'static str {
+        FACILITIES.iter().find(|(facility, _)| SyslogFacility(*facility) == *self).map(|(_, name)| *name).unwrap_or("user")
+    }
+}
+
+impl PartialEq for SyslogFacility {
+    fn eq(&self, other: &Self) -> bool {
+        self.code() == other.code()
+    }
+}
+
+impl Eq for SyslogFacility {}
+
+impl FromStr for SyslogFacility {
+    type Err = ParseNameError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = s.trim().to_lowercase();
+        let name = name.strip_prefix("log_").unwrap_or(&name);
+        let facility = match name.parse::<u8>() {
+            Ok(code) => Self::from_code(code),
+            Err(_) => FACILITIES.iter().find(|(_, n)| *n == name).map(|(facility, _)| SyslogFacility(*facility)),
+        };
+        facility.ok_or_else(|| ParseNameError { kind: "facility", value: s.to_string() })
+    }
+}
+
+impl fmt::Display for SyslogFacility {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl From<Facility> for SyslogFacility {
+    fn from(facility: Facility) -> Self {
+        SyslogFacility(facility)
+    }
+}
+
+impl From<SyslogFacility> for Facility {
+    fn from(facility: SyslogFacility) -> Self {
+        facility.0
+    }
+}
+
+/// The severity of a syslog message, most severe first.
+/// <br>
+/// Parsed from its name (`err`, `warning`, ...), the aliases rsyslog accepts (`error`, `warn`, `crit`, `panic`)
+/// or its code (`0` to `7`), displayed as its name.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SyslogSeverity {
+    Emerg = 0,
+    Alert = 1,
+    Crit = 2,
+    Err = 3,
+    Warning = 4,
+    Notice = 5,
+    Info = 6,
+    Debug = 7,
+}
+
+impl SyslogSeverity {
+    const ALL: [SyslogSeverity; 8] = [
+        SyslogSeverity::Emerg, SyslogSeverity::Alert, SyslogSeverity::Crit, SyslogSeverity::Err,
+        SyslogSeverity::Warning, SyslogSeverity::Notice, SyslogSeverity::Info, SyslogSeverity::Debug,
+    ];
+
+    pub fn from_code(code: u8) -> Option<Self> {
+        Self::ALL.get(code as usize).copied()
+    }
+
+    pub fn code(&self) -> u8 {
+        *self as u8
+    }
+
+    /// eg: "err", "warning"
+    pub fn name(&self) -> &'static str {
+        match self {
+            SyslogSeverity::Emerg => "emerg",
+            SyslogSeverity::Alert => "alert",
+            SyslogSeverity::Crit => "crit",
+            SyslogSeverity::Err => "err",
+            SyslogSeverity::Warning => "warning",
+            SyslogSeverity::Notice => "notice",
+            SyslogSeverity::Info => "info",
+            SyslogSeverity::Debug => "debug",
+        }
+    }
+}
+
+impl FromStr for SyslogSeverity {
+    type Err = ParseNameError;
+
+    fn from_str(s: &str) -> Result<Self, ParseNameError> {
+        let name = s.trim().to_lowercase();
+        let name = name.strip_prefix("log_").unwrap_or(&name);
+        let severity = match name {
+            "panic" => Some(SyslogSeverity::Emerg),
+            "critical" => Some(SyslogSeverity::Crit),
+            "error" => Some(SyslogSeverity::Err),
+            "warn" => Some(SyslogSeverity::Warning),
+            _ => match name.parse::<u8>() {
+                Ok(code) => Self::from_code(code),
+                Err(_) => Self::ALL.iter().find(|severity| severity.name() == name).copied(),
+            },
+        };
+        severity.ok_or_else(|| ParseNameError { kind: "severity", value: s.to_string() })
+    }
+}
+
+impl fmt::Display for SyslogSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// The syslog severity of the records of each level, and optionally of each target.
+/// <br>
+/// default: error -> err, warn -> warning, info -> info, debug -> debug, trace -> debug
+/// <br>
+/// A target matches the records whose target starts with it, the longest one wins,
+/// the levels not mapped for a target use the mapping of the level, eg:
+/// ```ignore
+/// SeverityMap::new()
+///     .with_level(Level::Error, SyslogSeverity::Crit)
+///     .with_target("audit", None, SyslogSeverity::Notice)
+///     .with_target("audit", Some(Level::Error), SyslogSeverity::Alert)
+/// ```
+/// Also parsed from config strings, `[target/]level=severity` separated by commas, `*` for all levels,
+/// eg: `"error=crit, audit/*=notice, audit/error=alert"` (what is not given keeps its default).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeverityMap {
+    /// indexed by `level as usize - 1`
+    levels: [SyslogSeverity; 5],
+    targets: Vec<(String, [Option<SyslogSeverity>; 5])>,
+}
+
+impl Default for SeverityMap {
+    fn default() -> Self {
+        Self {
+            levels: [SyslogSeverity::Err, SyslogSeverity::Warning, SyslogSeverity::Info, SyslogSeverity::Debug, SyslogSeverity::Debug],
+            targets: vec![],
+        }
+    }
+}
+
+impl SeverityMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// send the records of `level` with `severity`
+    pub fn with_level(mut self, level: Level, severity: SyslogSeverity) -> Self {
+        self.levels[level as usize - 1] = severity;
+        self
+    }
+
+    /// send the records of `target` at `level` (None: at any level) with `severity`
+    pub fn with_target(mut self, target: &str, level: Option<Level>, severity: SyslogSeverity) -> Self {
+        let index = match self.targets.iter().position(|(t, _)| t == target) {
+            Some(index) => index,
+            None => {
+                self.targets.push((target.to_string(), [None; 5]));
+                self.targets.len() - 1
+            }
+        };
+        let levels = &mut self.targets[index].1;
+        match level {
+            Some(level) => levels[level as usize - 1] = Some(severity),
+            None => *levels = [Some(severity); 5],
+        }
+        self
+    }
+
+    /// the severity of `level`, whatever the target
+    pub fn severity_of(&self, level: Level) -> SyslogSeverity {
+        self.levels[level as usize - 1]
+    }
+
+    /// the severity of a record of `target` at `level`
+    /// <br>
+    /// A target mapping covers its submodules, eg: `audit` covers `audit::login` but not `auditor`.
+    pub fn severity_for(&self, target: &str, level: Level) -> SyslogSeverity {
+        self.targets
+            .iter()
+            .filter(|(t, levels)| covers(t, target) && levels[level as usize - 1].is_some())
+            .max_by_key(|(t, _)| t.len())
+            .and_then(|(_, levels)| levels[level as usize - 1])
+            .unwrap_or_else(|| self.severity_of(level))
+    }
+}
+
+/// `module` is `target` or one of its submodules
+pub(crate) fn covers(module: &str, target: &str) -> bool {
+    target.strip_prefix(module).is_some_and(|rest| rest.is_empty() || rest.starts_with("::"))
+}
+
+impl FromStr for SeverityMap {
+    type Err = ParseNameError;
+
+    fn from_str(s: &str) -> Result<Self, ParseNameError> {
+        s.split(',')
+            .map(str::trim)
+            .filter(|pair| !pair.is_empty())
+            .try_fold(SeverityMap::default(), |map, pair| {
+                let invalid = || ParseNameError { kind: "severity mapping", value: pair.to_string() };
+                let (key, severity) = pair.split_once('=').ok_or_else(invalid)?;
+                let severity = severity.parse()?;
+                let (target, level) = match key.trim().rsplit_once('/') {
+                    Some((target, level)) => (Some(target), level),
+                    None => (None, key.trim()),
+                };
+                let level = match level {
+                    "*" => None,
+                    level => Some(Level::from_str(level).map_err(|_| invalid())?),
+                };
+                match (target, level) {
+                    (Some(target), level) => Ok(map.with_target(target, level, severity)),
+                    (None, Some(level)) => Ok(map.with_level(level, severity)),
+                    (None, None) => Err(invalid()),
+                }
+            })
+    }
+}
+
+impl fmt::Display for SeverityMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut pairs: Vec<String> = Level::iter()
+            .map(|level| format!("{}={}", level.as_str().to_lowercase(), self.severity_of(level)))
+            .collect();
+        for (target, levels) in &self.targets {
+            pairs.extend(Level::iter().filter_map(|level| {
+                levels[level as usize - 1].map(|severity| format!("{}/{}={}", target, level.as_str().to_lowercase(), severity))
+            }));
+        }
+        f.write_str(&pairs.join(","))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_syslog_facility() {
+        for name in ["local1", "LOCAL1", "log_local1", "LOG_LOCAL1", "17"] {
+            assert_eq!(name.parse::<SyslogFacility>(), Ok(SyslogFacility(Facility::LOG_LOCAL1)), "{}", name);
+        }
+        assert_eq!("daemon".parse::<SyslogFacility>().unwrap().code(), 3);
+        for (facility, name) in FACILITIES {
+            let facility = SyslogFacility(facility);
+            assert_eq!(SyslogFacility::from_code(facility.code()), Some(facility));
+            assert_eq!(facility.to_string(), name);
+            assert_eq!(name.parse::<SyslogFacility>(), Ok(facility));
+        }
+        assert_eq!("local8".parse::<SyslogFacility>().unwrap_err().to_string(), "unknown syslog facility: \"local8\"");
+        assert!("12".parse::<SyslogFacility>().is_err());
+        assert!(matches!(Facility::from(SyslogFacility::from_code(23).unwrap()), Facility::LOG_LOCAL7));
+    }
+
+    #[test]
+    fn test_syslog_severity() {
+        assert_eq!("error".parse(), Ok(SyslogSeverity::Err));
+        assert_eq!("WARN".parse(), Ok(SyslogSeverity::Warning));
+        assert_eq!("5".parse(), Ok(SyslogSeverity::Notice));
+        for severity in SyslogSeverity::ALL {
+            assert_eq!(severity.to_string().parse(), Ok(severity));
+            assert_eq!(SyslogSeverity::from_code(severity.code()), Some(severity));
+        }
+        assert!("8".parse::<SyslogSeverity>().is_err());
+    }
+
+    #[test]
+    fn test_severity_map() {
+        let map: SeverityMap = "error=crit, info=notice".parse().unwrap();
+        assert_eq!(map.severity_of(Level::Error), SyslogSeverity::Crit);
+        assert_eq!(map.severity_of(Level::Warn), SyslogSeverity::Warning);
+        assert_eq!(map.severity_of(Level::Info), SyslogSeverity::Notice);
+        assert_eq!(map.to_string(), "error=crit,warn=warning,info=notice,debug=debug,trace=debug");
+        assert_eq!(map.to_string().parse(), Ok(map));
+        assert_eq!("".parse(), Ok(SeverityMap::default()));
+        assert!("error".parse::<SeverityMap>().is_err());
+        assert!("fatal=crit".parse::<SeverityMap>().is_err());
+        assert!("error=fatal".parse::<SeverityMap>().is_err());
+    }
+
+    #[test]
+    fn test_severity_map_targets() {
+        let map = SeverityMap::new()
+            .with_level(Level::Error, SyslogSeverity::Crit)
+            .with_target("audit", None, SyslogSeverity::Notice)
+            .with_target("audit::login", Some(Level::Error), SyslogSeverity::Alert);
+        assert_eq!(map.severity_for("audit", Level::Info), SyslogSeverity::Notice);
+        assert_eq!(map.severity_for("audit::login", Level::Error), SyslogSeverity::Alert);
+        // the longest target mapping the level wins
+        assert_eq!(map.severity_for("audit::login", Level::Warn), SyslogSeverity::Notice);
+        assert_eq!(map.severity_for("portal", Level::Error), SyslogSeverity::Crit);
+        assert_eq!(map.severity_for("portal", Level::Warn), SyslogSeverity::Warning);
+        // a sibling sharing the prefix is not a submodule
+        assert_eq!(map.severity_for("auditor", Level::Info), SyslogSeverity::Info);
+        assert_eq!(map.severity_for("audit::login_history", Level::Error), SyslogSeverity::Notice);
+
+        let parsed: SeverityMap = "error=crit, audit/*=notice, audit::login/error=alert".parse().unwrap();
+        assert_eq!(parsed, map);
+        assert_eq!(parsed.to_string().parse(), Ok(map));
+        assert!("*=notice".parse::<SeverityMap>().is_err());
+        assert!("audit/fatal=notice".parse::<SeverityMap>().is_err());
+    }
+}
@@ -0,0 +1,251 @@
+use std::io::{self, BufRead, BufReader, Read};
+use std::net::{SocketAddr, TcpListener, TcpStream, UdpSocket};
+#[cfg(unix)]
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{channel, Receiver, Sender};
+use std::sync::Arc;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+pub use crate::parse::SyslogMessage;
+
+/// how often the receiving threads check if the receiver was dropped
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// Receives syslog messages on an ephemeral local port (or a unix socket), for tests to assert on what was actually sent, eg:
+/// ```ignore
+/// let receiver = SyslogReceiver::udp()?;
+/// start_udp_logger(Facility::LOG_LOCAL1, "portal", "0.0.0.0:0".parse()?, receiver.local_addr().unwrap(), LevelFilter::Info);
+/// warn!("disk almost full");
+/// let received = receiver.wait_for(1, Duration::from_secs(3));
+/// assert_eq!(received[0].severity_name(), "warning");
+/// assert_eq!(received[0].message, "disk almost full");
+/// ```
+/// What can't be parsed is received as a message with facility user and severity emerg.
+/// <br>
+/// Outside of this crate, needs the `test-helpers` feature.
+pub struct SyslogReceiver {
+    local_addr: Option<SocketAddr>,
+    #[cfg(unix)]
+    path: Option<PathBuf>,
+    messages: Receiver<SyslogMessage>,
+    stop: Arc<AtomicBool>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl SyslogReceiver {
+    /// bind 127.0.0.1 on an ephemeral udp port, one message per datagram
+    pub fn udp() -> io::Result<Self> {
+        let socket = UdpSocket::bind("127.0.0.1:0")?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let local_addr = socket.local_addr()?;
+        Ok(Self::start(Some(local_addr), move |stop, sender| {
+            let mut buffer = vec![0; 65536];
+            while !stop.load(Ordering::SeqCst) {
+                if let Ok(len) = socket.recv(&mut buffer) {
+                    send(&sender, &String::from_utf8_lossy(&buffer[..len]));
+                }
+            }
+        }))
+    }
+
+    /// bind 127.0.0.1 on an ephemeral tcp port, messages framed by octet counting (RFC 6587) or by newlines
+    pub fn tcp() -> io::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let local_addr = listener.local_addr()?;
+        Ok(Self::start(Some(local_addr), move |stop, sender| {
+            let mut connections = vec![];
+            while !stop.load(Ordering::SeqCst) {
+                match listener.accept() {
+                    Ok((stream, _)) => {
+                        let (stop, sender) = (stop.clone(), sender.clone());
+                        connections.push(std::thread::spawn(move || receive_tcp(stream, &stop, &sender)));
+                    }
+                    Err(_) => std::thread::sleep(POLL_INTERVAL),
+                }
+            }
+            for connection in connections {
+                connection.join().ok();
+            }
+        }))
+    }
+
+    /// bind a unix datagram socket at `path`, removed when the receiver is dropped
+    #[cfg(unix)]
+    pub fn unix(path: impl AsRef<Path>) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let socket = std::os::unix::net::UnixDatagram::bind(&path)?;
+        socket.set_read_timeout(Some(POLL_INTERVAL))?;
+        let mut receiver = Self::start(None, move |stop, sender| {
+            let mut buffer = vec![0; 65536];
+            while !stop.load(Ordering::SeqCst) {
+                if let Ok(len) = socket.recv(&mut buffer) {
+                    send(&sender, &String::from_utf8_lossy(&buffer[..len]));
+                }
+            }
+        });
+        receiver.path = Some(path);
+        Ok(receiver)
+    }
+
+    fn start(local_addr: Option<SocketAddr>, receive: impl FnOnce(Arc<AtomicBool>, Sender<SyslogMessage>) + Send + 'static) -> Self {
+        let (sender, messages) = channel();
+        let stop = Arc::new(AtomicBool::new(false));
+        let thread = {
+            let stop = stop.clone();
+            std::thread::Builder::new()
+                .name("fblog-syslog-receiver".to_string())
+                .spawn(move || receive(stop, sender))
+                .expect("could not spawn the syslog receiver thread")
+        };
+        Self {
+            local_addr,
+            #[cfg(unix)]
+            path: None,
+            messages,
+            stop,
+            thread: Some(thread),
+        }
+    }
+
+    /// the address to send to, None for a unix socket
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.local_addr
+    }
+
+    /// the next message, waiting at most `timeout`
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<SyslogMessage> {
+        self.messages.recv_timeout(timeout).ok()
+    }
+
+    /// the messages received so far, without waiting
+    pub fn received(&self) -> Vec<SyslogMessage> {
+        self.messages.try_iter().collect()
+    }
+
+    /// wait until `count` messages are received or `timeout` has elapsed, returns the messages received
+    pub fn wait_for(&self, count: usize, timeout: Duration) -> Vec<SyslogMessage> {
+        let deadline = Instant::now() + timeout;
+        let mut received = vec![];
+        while received.len() < count {
+            match self.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Some(message) => received.push(message),
+                None => break,
+            }
+        }
+        received
+    }
+
+    /// wait until a message matching `last` is received or `timeout` has elapsed, returns the messages received, up to that one
+    pub fn wait_until(&self, timeout: Duration, last: impl Fn(&SyslogMessage) -> bool) -> Vec<SyslogMessage> {
+        let deadline = Instant::now() + timeout;
+        let mut received = vec![];
+        while let Some(message) = self.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+            let is_last = last(&message);
+            received.push(message);
+            if is_last {
+                break;
+            }
+        }
+        received
+    }
+}
+
+impl Drop for SyslogReceiver {
+    fn drop(&mut self) {
+        self.stop.store(true, Ordering::SeqCst);
+        if let Some(thread) = self.thread.take() {
+            thread.join().ok();
+        }
+        #[cfg(unix)]
+        if let Some(path) = &self.path {
+            std::fs::remove_file(path).ok();
+        }
+    }
+}
+
+fn send(sender: &Sender<SyslogMessage>, text: &str) {
+    let message = SyslogMessage::parse(text).unwrap_or_else(|| SyslogMessage::new(text));
+    sender.send(message).ok();
+}
+
+fn receive_tcp(stream: TcpStream, stop: &AtomicBool, sender: &Sender<SyslogMessage>) {
+    if stream.set_read_timeout(Some(POLL_INTERVAL)).is_err() {
+        return;
+    }
+    let mut reader = BufReader::new(stream);
+    while !stop.load(Ordering::SeqCst) {
+        let first = match reader.fill_buf() {
+            Ok([]) => return,
+            Ok(buffer) => buffer[0],
+            Err(err) if matches!(err.kind(), io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut) => continue,
+            Err(_) => return,
+        };
+        let frame = if first.is_ascii_digit() {
+            // octet counting: "<len> <message>"
+            let mut len = vec![];
+            if reader.read_until(b' ', &mut len).is_err() {
+                return;
+            }
+            let len: usize = match String::from_utf8_lossy(&len).trim().parse() {
+                Ok(len) => len,
+                Err(_) => return,
+            };
+            let mut frame = vec![0; len];
+            if reader.read_exact(&mut frame).is_err() {
+                return;
+            }
+            frame
+        } else {
+            let mut frame = vec![];
+            if reader.read_until(b'\n', &mut frame).is_err() {
+                return;
+            }
+            if frame.last() == Some(&b'\n') {
+                frame.pop();
+            }
+            frame
+        };
+        send(sender, &String::from_utf8_lossy(&frame));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Write;
+
+    use super::*;
+
+    #[test]
+    fn test_syslog_receiver() {
+        let receiver = SyslogReceiver::udp().unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        socket.send_to(b"<12>Oct 19 08:03:29 host portal[7]: disk almost full", receiver.local_addr().unwrap()).unwrap();
+        socket.send_to(b"garbage", receiver.local_addr().unwrap()).unwrap();
+        let received = receiver.wait_for(2, Duration::from_secs(3));
+        assert_eq!(received.len(), 2);
+        assert_eq!((received[0].severity_name(), received[0].message.as_str()), ("warning", "disk almost full"));
+        assert_eq!(received[1].message, "garbage");
+
+        let receiver = SyslogReceiver::tcp().unwrap();
+        let mut stream = TcpStream::connect(receiver.local_addr().unwrap()).unwrap();
+        stream.write_all(b"<14>portal: first\n23 <14>portal: second\nline").unwrap();
+        let received = receiver.wait_for(2, Duration::from_secs(3));
+        assert_eq!(received.iter().map(|message| message.message.as_str()).collect::<Vec<_>>(), vec!["first", "second\nline"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_syslog_receiver_unix() {
+        let path = std::env::temp_dir().join(format!("fblog-syslog-receiver-{}.sock", std::process::id()));
+        let receiver = SyslogReceiver::unix(&path).unwrap();
+        assert_eq!(receiver.local_addr(), None);
+        let socket = std::os::unix::net::UnixDatagram::unbound().unwrap();
+        socket.send_to(b"<11>portal[7]: failed", &path).unwrap();
+        assert_eq!(receiver.recv_timeout(Duration::from_secs(3)).unwrap().message, "failed");
+        drop(receiver);
+        assert!(!path.exists());
+    }
+}
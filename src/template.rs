@@ -0,0 +1,369 @@
+use std::fmt;
+use std::io::Write;
+
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, FixedOffset, Utc};
+use log::Record;
+use once_cell::sync::{Lazy, OnceCell};
+
+use crate::color;
+use crate::key_values::AsText;
+
+/// The line layout used by `detailed_format` and `get_default_env_logger_builder`
+/// unless another one is set with `template::init`.
+pub const DEFAULT_TEMPLATE: &str = "[{level}] {time} {host}-{ip} {module} {file}:{line} {msg}{kv}";
+
+const DEFAULT_TIME_FORMAT: &str = "%Y-%m-%d %H:%M:%S%.6f %:z";
+
+/// the offset of `{time}` unless set with `Template::with_offset`: +08:00, as the log lines have always been
+const DEFAULT_UTC_OFFSET_SECS: i32 = 8 * 3600;
+
+static GLOBAL_TEMPLATE: OnceCell<Template> = OnceCell::new();
+static LOCAL_HOST_NAME: Lazy<String> = Lazy::new(crate::hostname);
+static LOCAL_IP: Lazy<String> = Lazy::new(crate::get_proper_ip);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    /// `{name}` is not a known placeholder
+    UnknownPlaceholder(String),
+    /// `{...` without closing `}`, or a single `}` without opening `{`
+    UnmatchedBrace(usize),
+    /// the argument after ':' is not valid for the placeholder, eg: `{level:abc}`
+    InvalidArgument(String),
+    /// `template::init` was called more than once
+    AlreadyInitialized,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TemplateError::UnknownPlaceholder(name) => write!(f, "unknown placeholder: {{{}}}", name),
+            TemplateError::UnmatchedBrace(pos) => write!(f, "unmatched brace at position {}", pos),
+            TemplateError::InvalidArgument(placeholder) => write!(f, "invalid argument in placeholder: {{{}}}", placeholder),
+            TemplateError::AlreadyInitialized => write!(f, "log line template already initialized"),
+        }
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Level,
+    Target,
+    Module,
+    File,
+    Line,
+    Host,
+    Ip,
+    Msg,
+    Kv,
+    Thread,
+    ThreadId,
+    Pid,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Align {
+    None,
+    Left(usize),
+    Right(usize),
+}
+
+#[derive(Debug, Clone)]
+enum Piece {
+    Literal(String),
+    Time(String),
+    Field(Field, Align),
+}
+
+/// A compiled log line layout.
+/// <br>
+/// Placeholders are written as `{name}` or `{name:arg}`, use `{{` and `}}` for literal braces:
+/// * `{time}`: the time at a fixed offset (+08:00 by default, see `with_offset`), `arg` is a strftime format,
+///   eg: `{time:%H:%M:%S%.3f}`
+/// * `{level}`, `{target}`, `{module}`, `{file}`, `{line}`, `{msg}`
+/// * `{host}`, `{ip}`: hostname and ip of this machine
+/// * `{kv}`: key-values of the record, as ` k1=v1 k2=v2`
+/// * `{thread}`, `{thread_id}`, `{pid}`
+///
+/// For every placeholder except `{time}`, `arg` is a width: `{level:5}` pads to the left, `{line:>4}` pads to the right.
+/// <br>
+/// eg: `"{time:%H:%M:%S%.3f} {level:5} {target} {msg}"` -> `16:07:13.381 INFO  portal::oauth2 started`
+#[derive(Debug, Clone)]
+pub struct Template {
+    pieces: Vec<Piece>,
+    offset: FixedOffset,
+}
+
+impl Template {
+    pub fn compile(template: &str) -> Result<Template, TemplateError> {
+        let mut pieces = vec![];
+        let mut literal = String::new();
+        let mut chars = template.char_indices().peekable();
+        while let Some((pos, c)) = chars.next() {
+            match c {
+                '{' if chars.peek().map(|(_, c)| *c) == Some('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.peek().map(|(_, c)| *c) == Some('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let placeholder: String = chars.by_ref().map(|(_, c)| c).take_while(|c| *c != '}').collect();
+                    if !template[pos..].contains('}') {
+                        return Err(TemplateError::UnmatchedBrace(pos));
+                    }
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Self::compile_placeholder(&placeholder)?);
+                }
+                '}' => return Err(TemplateError::UnmatchedBrace(pos)),
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+        let offset = FixedOffset::east_opt(DEFAULT_UTC_OFFSET_SECS).expect("default offset must be valid");
+        Ok(Template { pieces, offset })
+    }
+
+    /// write `{time}` at `offset` instead of +08:00,
+    /// eg: `*chrono::Local::now().offset()` for the offset of the machine when the logger starts
+    pub fn with_offset(mut self, offset: FixedOffset) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// the offset `{time}` is written at
+    pub fn offset(&self) -> FixedOffset {
+        self.offset
+    }
+
+    fn now(&self) -> DateTime<FixedOffset> {
+        Utc::now().with_timezone(&self.offset)
+    }
+
+    fn compile_placeholder(placeholder: &str) -> Result<Piece, TemplateError> {
+        let (name, arg) = match placeholder.split_once(':') {
+            Some((name, arg)) => (name.trim(), Some(arg)),
+            None => (placeholder.trim(), None),
+        };
+        let field = match name {
+            "time" => {
+                let format = arg.unwrap_or(DEFAULT_TIME_FORMAT);
+                if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                    return Err(TemplateError::InvalidArgument(placeholder.to_string()));
+                }
+                return Ok(Piece::Time(format.to_string()));
+            }
+            "level" => Field::Level,
+            "target" => Field::Target,
+            "module" => Field::Module,
+            "file" => Field::File,
+            "line" => Field::Line,
+            "host" => Field::Host,
+            "ip" => Field::Ip,
+            "msg" => Field::Msg,
+            "kv" => Field::Kv,
+            "thread" => Field::Thread,
+            "thread_id" => Field::ThreadId,
+            "pid" => Field::Pid,
+            _ => return Err(TemplateError::UnknownPlaceholder(name.to_string())),
+        };
+        let align = match arg.map(str::trim) {
+            None => Align::None,
+            Some(width) => {
+                let (right, width) = match width.strip_prefix('>') {
+                    Some(width) => (true, width),
+                    None => (false, width.strip_prefix('<').unwrap_or(width)),
+                };
+                let width = width.parse()
+                    .map_err(|_| TemplateError::InvalidArgument(placeholder.to_string()))?;
+                if right { Align::Right(width) } else { Align::Left(width) }
+            }
+        };
+        Ok(Piece::Field(field, align))
+    }
+
+    /// write a record with this layout, without trailing newline.
+    pub fn write(&self, w: &mut dyn Write, record: &Record) -> std::io::Result<()> {
+        let now = self.now();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(s) => w.write_all(s.as_bytes())?,
+                Piece::Time(format) => write!(w, "{}", now.format(format))?,
+                Piece::Field(field, align) => write_aligned(w, &render_field(*field, record), *align)?,
+            }
+        }
+        Ok(())
+    }
+
+    /// same as `write`, but the level tag and the message are colored by level, other fields are dimmed.
+    pub fn write_colored(&self, w: &mut dyn Write, record: &Record) -> std::io::Result<()> {
+        let level_style = color::level_style(record.level());
+        self.write_styled(w, record, &mut |w, part, text| {
+            let style = match part {
+                Part::Level => level_style,
+                Part::Other => color::DIM,
+            };
+            write!(w, "{}{}{}", style, text, color::RESET)
+        })
+    }
+
+    /// same as `write`, the time and the fields are written by `styled`, eg: with the styles of env_logger
+    pub fn write_styled(&self, w: &mut dyn Write, record: &Record,
+                        styled: &mut dyn FnMut(&mut dyn Write, Part, &str) -> std::io::Result<()>) -> std::io::Result<()> {
+        let now = self.now();
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(s) => w.write_all(s.as_bytes())?,
+                Piece::Time(format) => styled(w, Part::Other, &now.format(format).to_string())?,
+                Piece::Field(field, align) => {
+                    let part = match field {
+                        Field::Level | Field::Msg => Part::Level,
+                        _ => Part::Other,
+                    };
+                    let mut aligned = vec![];
+                    write_aligned(&mut aligned, &render_field(*field, record), *align)?;
+                    styled(w, part, &String::from_utf8_lossy(&aligned))?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The parts of a line, as given to the `styled` of `Template::write_styled`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Part {
+    /// the level tag and the message, colored by level
+    Level,
+    /// the time and the other fields, dimmed
+    Other,
+}
+
+impl Default for Template {
+    fn default() -> Self {
+        Template::compile(DEFAULT_TEMPLATE).expect("default template must compile")
+    }
+}
+
+/// compile `template` and use it for all local log lines (env_logger and flexi_logger),
+/// must be called before starting the logger.
+pub fn init(template: &str) -> Result<(), TemplateError> {
+    init_with(Template::compile(template)?)
+}
+
+/// same as `init`, with a template already compiled, eg: `Template::compile(..)?.with_offset(..)`
+pub fn init_with(template: Template) -> Result<(), TemplateError> {
+    GLOBAL_TEMPLATE.set(template).map_err(|_| TemplateError::AlreadyInitialized)
+}
+
+/// the template set by `init`, or the one compiled from DEFAULT_TEMPLATE.
+pub fn global() -> &'static Template {
+    GLOBAL_TEMPLATE.get_or_init(Template::default)
+}
+
+fn render_field(field: Field, record: &Record) -> String {
+    match field {
+        Field::Level => record.level().to_string(),
+        Field::Target => record.target().to_string(),
+        Field::Module => record.module_path().unwrap_or("<unnamed>").to_string(),
+        // 1. if file path is relative, nothing changes.
+        // 2. if it is absolute, prefixed with "file://"
+        Field::File => record
+            .file()
+            .map(|p| if p.starts_with('/') { "file://".to_string() + p } else { p.to_string() })
+            .unwrap_or_else(|| "<unnamed>".to_string()),
+        Field::Line => record.line().unwrap_or(0).to_string(),
+        Field::Host => LOCAL_HOST_NAME.clone(),
+        Field::Ip => LOCAL_IP.clone(),
+        Field::Msg => record.args().to_string(),
+        Field::Kv => AsText(record.key_values()).to_string(),
+        Field::Thread => std::thread::current().name().unwrap_or("<unnamed>").to_string(),
+        // ThreadId(12) -> 12
+        Field::ThreadId => format!("{:?}", std::thread::current().id())
+            .trim_start_matches("ThreadId(")
+            .trim_end_matches(')')
+            .to_string(),
+        Field::Pid => std::process::id().to_string(),
+    }
+}
+
+fn write_aligned(w: &mut dyn Write, value: &str, align: Align) -> std::io::Result<()> {
+    match align {
+        Align::None => write!(w, "{}", value),
+        Align::Left(width) => write!(w, "{:<width$}", value, width = width),
+        Align::Right(width) => write!(w, "{:>width$}", value, width = width),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use log::Level;
+
+    use super::*;
+
+    fn render(template: &str) -> String {
+        let template = Template::compile(template).unwrap();
+        let kvs: &[(&str, i32)] = &[("user_id", 42)];
+        let mut buf = vec![];
+        template.write(&mut buf, &Record::builder()
+            .level(Level::Info)
+            .target("portal::oauth2")
+            .module_path(Some("portal::oauth2::http"))
+            .file(Some("src/oauth2/http.rs"))
+            .line(Some(205))
+            .key_values(&kvs)
+            .args(format_args!("started"))
+            .build()).unwrap();
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn test_render() {
+        assert_eq!(render("{level:5}|{target}|{msg}"), "INFO |portal::oauth2|started");
+        assert_eq!(render("[{level}] {module} {file}:{line:>4} {msg}{kv}"),
+                   "[INFO] portal::oauth2::http src/oauth2/http.rs: 205 started user_id=42");
+        assert_eq!(render("{{{msg}}}"), "{started}");
+        assert_eq!(render("{pid}"), std::process::id().to_string());
+        assert_eq!(render("{time:%Y}").len(), 4);
+        assert_eq!(render("{time:%:z}"), "+08:00");
+    }
+
+    #[test]
+    fn test_with_offset() {
+        let template = Template::compile("{time:%:z} {msg}").unwrap().with_offset(FixedOffset::west_opt(3600).unwrap());
+        let mut buf = vec![];
+        template.write(&mut buf, &Record::builder().args(format_args!("started")).build()).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(), "-01:00 started");
+    }
+
+    #[test]
+    fn test_write_colored() {
+        let template = Template::compile("[{level}] {target} {msg}").unwrap();
+        let mut buf = vec![];
+        template.write_colored(&mut buf, &Record::builder()
+            .level(Level::Warn)
+            .target("portal")
+            .args(format_args!("slow"))
+            .build()).unwrap();
+        assert_eq!(String::from_utf8(buf).unwrap(),
+                   "[\x1b[33mWARN\x1b[0m] \x1b[2mportal\x1b[0m \x1b[33mslow\x1b[0m");
+    }
+
+    #[test]
+    fn test_compile_error() {
+        assert_eq!(Template::compile("{msg").unwrap_err(), TemplateError::UnmatchedBrace(0));
+        assert_eq!(Template::compile("msg}").unwrap_err(), TemplateError::UnmatchedBrace(3));
+        assert_eq!(Template::compile("{nope}").unwrap_err(), TemplateError::UnknownPlaceholder("nope".to_string()));
+        assert_eq!(Template::compile("{level:x}").unwrap_err(), TemplateError::InvalidArgument("level:x".to_string()));
+        assert_eq!(Template::compile("{time:%Q}").unwrap_err(), TemplateError::InvalidArgument("time:%Q".to_string()));
+        assert!(Template::compile(DEFAULT_TEMPLATE).is_ok());
+    }
+}
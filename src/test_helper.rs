@@ -1,24 +1,274 @@
+#[cfg(any(test, feature = "test-helpers"))]
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::marker::PhantomData;
+#[cfg(any(test, feature = "test-helpers"))]
+use std::rc::Rc;
+use std::sync::{Mutex, MutexGuard};
+
 use env_logger::Builder;
+#[cfg(any(test, feature = "test-helpers"))]
+use log::{Level, Record};
+
+use crate::config_for_env_logger::{get_default_env_logger_builder, try_init};
+#[cfg(any(test, feature = "test-helpers"))]
+use crate::key_values::collect;
+pub use crate::parse::SyslogMessage;
+#[cfg(any(test, feature = "test-helpers"))]
+pub use crate::syslog_receiver::SyslogReceiver;
 
-use crate::config_for_env_logger::get_default_env_logger_builder;
+#[cfg(any(test, feature = "test-helpers"))]
+type Records = Rc<RefCell<Vec<CapturedRecord>>>;
+
+#[cfg(any(test, feature = "test-helpers"))]
+thread_local! {
+    /// the captures started on the current thread, innermost last
+    static CAPTURES: RefCell<Vec<Records>> = const { RefCell::new(vec![]) };
+    /// how many times the logger was flushed on the current thread while capturing
+    static FLUSHES: Cell<usize> = const { Cell::new(0) };
+}
 
+/// Start an env_logger for tests with `log_spec`, unless the one started by the previous call with the same spec is still started.
+/// <br>
+/// A logger started otherwise (eg: by another test, or shut down since) is replaced,
+/// hold `logger_lock()` while using it so that other tests can't replace it in turn.
 pub fn try_init_logger(log_spec: &str) {
-    get_default_env_logger_builder(log_spec)
-        .is_test(true)
-        .try_init()
-        .map_err(|err| {
-            println!("log init err: {}", err);
-            err
-        })
-        .ok();
+    let _lock = logger_lock();
+    let mut started = G_TEST_LOGGER.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if let (Some((generation, spec)), Some(current)) = (started.as_ref(), crate::control::handle().generation()) {
+        if *generation == current && spec == log_spec {
+            return;
+        }
+    }
+    match try_init(default_logger(log_spec)) {
+        Ok(()) => *started = crate::control::handle().generation().map(|generation| (generation, log_spec.to_string())),
+        Err(err) => println!("log init err: {}", err),
+    }
 }
 
+/// the generation (see `ControlHandle::generation`) and spec of the logger started by `try_init_logger`
+static G_TEST_LOGGER: Mutex<Option<(u64, String)>> = Mutex::new(None);
+
 pub fn default_logger(log_spec: &str) -> Builder {
     let mut builder = get_default_env_logger_builder(log_spec);
     builder.is_test(true);
     builder
 }
 
+static G_LOGGER_LOCK: Mutex<()> = Mutex::new(());
+
+thread_local! {
+    /// the lock held by the current thread, and how many `LoggerLock`s share it
+    static HELD_LOGGER_LOCK: RefCell<(usize, Option<MutexGuard<'static, ()>>)> = const { RefCell::new((0, None)) };
+}
+
+/// Hold it while a test starts and uses a logger, eg:
+/// ```ignore
+/// let _lock = test_helper::logger_lock();
+/// let _guard = LocalLog::new(true, false).start_local_logger("debug");
+/// ```
+/// Each start function replaces the logger of the process, so without it tests running in parallel
+/// would log into each other's sinks, and shut them down.
+/// <br>
+/// It can be taken again by the thread holding it, eg: `capture()` and `try_init_logger` take it too.
+pub fn logger_lock() -> LoggerLock {
+    HELD_LOGGER_LOCK.with(|held| {
+        let mut held = held.borrow_mut();
+        if held.0 == 0 {
+            held.1 = Some(G_LOGGER_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner()));
+        }
+        held.0 += 1;
+    });
+    LoggerLock { _not_send: PhantomData }
+}
+
+/// Returned by `logger_lock()`, the lock is released when the last one of the thread is dropped.
+#[must_use = "the lock is released when it is dropped"]
+pub struct LoggerLock {
+    _not_send: PhantomData<*const ()>,
+}
+
+impl Drop for LoggerLock {
+    fn drop(&mut self) {
+        let guard = HELD_LOGGER_LOCK.with(|held| {
+            let mut held = held.borrow_mut();
+            held.0 -= 1;
+            if held.0 == 0 { held.1.take() } else { None }
+        });
+        drop(guard);
+    }
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+/// A record logged while a `Capture` was active.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CapturedRecord {
+    pub level: Level,
+    pub target: String,
+    pub message: String,
+    pub key_values: Vec<(String, String)>,
+    pub file: Option<String>,
+    pub line: Option<u32>,
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl CapturedRecord {
+    pub fn key_value(&self, key: &str) -> Option<&str> {
+        self.key_values.iter().rev().find(|(k, _)| k == key).map(|(_, value)| value.as_str())
+    }
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+/// Start capturing the records logged on the current thread, at every level, until the returned `Capture` is dropped, eg:
+/// ```ignore
+/// let capture = test_helper::capture();
+/// connect("10.0.0.7:5432");
+/// assert_logged!(Level::Warn, contains "timeout");
+/// assert_eq!(capture.records()[0].key_value("retry"), Some("3"));
+/// ```
+/// Works with the global logger: if none is started (or it was shut down), the one of `try_init_logger("info")` is, so the records are also printed.
+/// <br>
+/// Holds `logger_lock()` until dropped, so tests using a capture don't replace each other's logger.
+/// Only records going through `pipeline` (all start functions of this crate) are captured.
+/// <br>
+/// As tests run in parallel on their own threads, records of other tests are not captured,
+/// neither are records logged on threads spawned by the test.
+/// <br>
+/// The records are captured after the stages of `pipeline`, eg: redacted.
+/// Outside of this crate, needs the `test-helpers` feature, eg: in the dev-dependencies of a service.
+pub fn capture() -> Capture {
+    let lock = logger_lock();
+    let records = Records::default();
+    CAPTURES.with(|captures| captures.borrow_mut().push(records.clone()));
+    crate::pipeline::add_capture();
+    if crate::control::handle().generation().is_none() {
+        try_init_logger("info");
+    }
+    Capture { records, flushes: FLUSHES.with(Cell::get), _lock: lock }
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+/// Returned by `capture()`, stops capturing when dropped.
+#[must_use = "capturing stops when the capture is dropped"]
+pub struct Capture {
+    records: Records,
+    /// `FLUSHES` when the capture started
+    flushes: usize,
+    _lock: LoggerLock,
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl Capture {
+    /// the records captured so far, oldest first
+    pub fn records(&self) -> Vec<CapturedRecord> {
+        self.records.borrow().clone()
+    }
+
+    pub fn clear(&self) {
+        self.records.borrow_mut().clear();
+    }
+
+    /// how many times the logger was flushed on the current thread since the capture started
+    pub fn flushes(&self) -> usize {
+        FLUSHES.with(Cell::get) - self.flushes
+    }
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+impl Drop for Capture {
+    fn drop(&mut self) {
+        CAPTURES.with(|captures| captures.borrow_mut().retain(|records| !Rc::ptr_eq(records, &self.records)));
+        crate::pipeline::remove_capture();
+    }
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+/// true if a capture is active on the current thread
+pub(crate) fn is_capturing() -> bool {
+    CAPTURES.with(|captures| !captures.borrow().is_empty())
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+/// called by `pipeline` for every record passing the stages (or kept by the ring buffer)
+pub(crate) fn capture_record(record: &Record) {
+    CAPTURES.with(|captures| {
+        let captures = captures.borrow();
+        if captures.is_empty() {
+            return;
+        }
+        let captured = CapturedRecord {
+            level: record.level(),
+            target: record.target().to_string(),
+            message: record.args().to_string(),
+            key_values: collect(record.key_values()),
+            file: record.file().map(str::to_string),
+            line: record.line(),
+        };
+        for records in captures.iter() {
+            records.borrow_mut().push(captured.clone());
+        }
+    });
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+/// called by `pipeline` when the logger is flushed
+pub(crate) fn capture_flush() {
+    if is_capturing() {
+        FLUSHES.with(|flushes| flushes.set(flushes.get() + 1));
+    }
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+/// the records of the innermost capture of the current thread
+pub fn captured() -> Vec<CapturedRecord> {
+    CAPTURES.with(|captures| captures.borrow().last().map(|records| records.borrow().clone()).unwrap_or_default())
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+/// used by `assert_logged!` and `assert_not_logged!`
+#[doc(hidden)]
+#[track_caller]
+pub fn __assert_logged(expected: bool, level: Level, matches: impl Fn(&str) -> bool, description: &str) {
+    let records = captured();
+    if records.iter().any(|record| record.level == level && matches(&record.message)) != expected {
+        let captured: Vec<String> = records.iter().map(|record| format!("  {} {} {}", record.level, record.target, record.message)).collect();
+        panic!("{} record at level {} with message {}, captured:\n{}",
+               if expected { "expected a" } else { "expected no" }, level, description, captured.join("\n"));
+    }
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+/// Assert that a record was logged at the level, on the current thread, since the innermost `test_helper::capture()`, eg:
+/// ```ignore
+/// assert_logged!(Level::Warn, contains "timeout");
+/// assert_logged!(Level::Info, eq "connected");
+/// ```
+#[macro_export]
+macro_rules! assert_logged {
+    ($level:expr, contains $text:expr) => ({
+        let text = $text;
+        $crate::test_helper::__assert_logged(true, $level, |message| message.contains(text), &format!("containing {:?}", text));
+    });
+    ($level:expr, eq $text:expr) => ({
+        let text = $text;
+        $crate::test_helper::__assert_logged(true, $level, |message| message == text, &format!("{:?}", text));
+    });
+}
+
+#[cfg(any(test, feature = "test-helpers"))]
+/// The opposite of `assert_logged!`.
+#[macro_export]
+macro_rules! assert_not_logged {
+    ($level:expr, contains $text:expr) => ({
+        let text = $text;
+        $crate::test_helper::__assert_logged(false, $level, |message| message.contains(text), &format!("containing {:?}", text));
+    });
+    ($level:expr, eq $text:expr) => ({
+        let text = $text;
+        $crate::test_helper::__assert_logged(false, $level, |message| message == text, &format!("{:?}", text));
+    });
+}
+
 #[cfg(test)]
 pub mod test {
     use log::*;
@@ -27,10 +277,55 @@ pub mod test {
 
     #[test]
     fn test_init_logger() {
+        let _lock = logger_lock();
         try_init_logger("debug");
         debug!("hello");
         info!("hello");
         warn!("hello");
         error!("hello");
+
+        // reused with the same spec, replaced with another one or once shut down
+        let generation = crate::control::handle().generation();
+        try_init_logger("debug");
+        assert_eq!(crate::control::handle().generation(), generation);
+        try_init_logger("trace");
+        assert_ne!(crate::control::handle().generation(), generation);
+        crate::control::handle().shutdown(std::time::Duration::from_secs(1)).unwrap();
+        let capture = capture();
+        assert!(crate::control::handle().generation().is_some());
+        trace!("hello");
+        assert_eq!(capture.records().len(), 1);
+    }
+
+    #[test]
+    fn test_capture() {
+        let capture = capture();
+        debug!(target: "portal::db", retry = 3; "connect timeout after {}ms", 500);
+        warn!("giving up");
+        {
+            let inner = super::capture();
+            info!("nested");
+            assert_eq!(inner.records().len(), 1);
+            assert_not_logged!(Level::Warn, contains "giving up");
+        }
+        std::thread::spawn(|| error!("other thread")).join().unwrap();
+
+        assert_logged!(Level::Debug, contains "timeout");
+        assert_logged!(Level::Warn, eq "giving up");
+        assert_not_logged!(Level::Warn, contains "timeout");
+        assert_not_logged!(Level::Error, contains "other thread");
+        let records = capture.records();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records[0].target, "portal::db");
+        assert_eq!(records[0].key_value("retry"), Some("3"));
+        assert_eq!(records[2].message, "nested");
+
+        capture.clear();
+        assert!(capture.records().is_empty());
+        drop(capture);
+        info!("not captured");
+        assert!(captured().is_empty());
+        let result = std::panic::catch_unwind(|| assert_logged!(Level::Info, contains "not captured"));
+        assert!(result.is_err());
     }
 }
@@ -0,0 +1,194 @@
+use std::fmt;
+
+use log::{Level, Log, Record};
+use tracing_core::field::{Field, Visit};
+use tracing_core::span::{Attributes, Id, Record as SpanRecord};
+use tracing_core::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::key_values::OwnedPairs;
+
+/// A `tracing_subscriber::Layer` forwarding `tracing` events to the started logger (syslog, console/file or env_logger),
+/// so events of eg: hyper, tonic, sqlx end up in the same sinks and formats as `log` records:
+/// * the names of the entered spans (outer ones first) are a prefix of the message, eg: `request: db_query: slow query`
+/// * the fields of the spans (outer ones first) and of the event become the key-values of the record,
+///   a field of the event replaces the one of a span with the same name
+/// ```ignore
+/// use tracing_subscriber::layer::SubscriberExt;
+/// tracing::subscriber::set_global_default(tracing_subscriber::registry().with(FblogLayer::new()))?;
+/// ```
+pub struct FblogLayer {
+    logger: Option<&'static dyn Log>,
+}
+
+impl FblogLayer {
+    /// forward to `log::logger()`
+    pub fn new() -> Self {
+        Self { logger: None }
+    }
+
+    /// forward to `logger` instead of `log::logger()`
+    pub fn with_logger(logger: &'static dyn Log) -> Self {
+        Self { logger: Some(logger) }
+    }
+
+    fn logger(&self) -> &dyn Log {
+        self.logger.unwrap_or_else(log::logger)
+    }
+}
+
+impl Default for FblogLayer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The fields recorded for a span, stored in its extensions.
+struct SpanFields(Vec<(String, String)>);
+
+/// Collects fields as strings, the `message` field apart.
+#[derive(Default)]
+struct FieldVisitor {
+    message: Option<String>,
+    fields: Vec<(String, String)>,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.record(field, value.to_string());
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        self.record(field, format!("{:?}", value));
+    }
+}
+
+impl FieldVisitor {
+    fn record(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = Some(value);
+        } else {
+            self.fields.push((field.name().to_string(), value));
+        }
+    }
+}
+
+fn to_log_level(level: &tracing_core::Level) -> Level {
+    match *level {
+        tracing_core::Level::ERROR => Level::Error,
+        tracing_core::Level::WARN => Level::Warn,
+        tracing_core::Level::INFO => Level::Info,
+        tracing_core::Level::DEBUG => Level::Debug,
+        tracing_core::Level::TRACE => Level::Trace,
+    }
+}
+
+impl<S> Layer<S> for FblogLayer
+    where S: Subscriber + for<'a> LookupSpan<'a>
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &SpanRecord<'_>, ctx: Context<'_, S>) {
+        let mut visitor = FieldVisitor::default();
+        values.record(&mut visitor);
+        if let Some(span) = ctx.span(id) {
+            let mut extensions = span.extensions_mut();
+            match extensions.get_mut::<SpanFields>() {
+                Some(SpanFields(fields)) => fields.extend(visitor.fields),
+                None => extensions.insert(SpanFields(visitor.fields)),
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: Context<'_, S>) {
+        let metadata = event.metadata();
+        let level = to_log_level(metadata.level());
+        let log_metadata = log::Metadata::builder().level(level).target(metadata.target()).build();
+        if level > log::max_level() || !self.logger().enabled(&log_metadata) {
+            return;
+        }
+
+        let mut prefix = String::new();
+        let mut fields = vec![];
+        if let Some(scope) = ctx.event_scope(event) {
+            for span in scope.from_root() {
+                prefix.push_str(span.name());
+                prefix.push_str(": ");
+                if let Some(SpanFields(span_fields)) = span.extensions().get::<SpanFields>() {
+                    fields.extend(span_fields.iter().cloned());
+                }
+            }
+        }
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+        fields.extend(visitor.fields);
+        let kvs = OwnedPairs(&fields);
+
+        self.logger().log(&Record::builder()
+            .metadata(log_metadata)
+            .module_path(metadata.module_path())
+            .file(metadata.file())
+            .line(metadata.line())
+            .key_values(&kvs)
+            .args(format_args!("{}{}", prefix, visitor.message.unwrap_or_default()))
+            .build());
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Mutex;
+
+    use log::Metadata;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+    use crate::key_values::AsText;
+
+    struct CaptureLog(Mutex<Vec<String>>);
+
+    impl Log for CaptureLog {
+        fn enabled(&self, metadata: &Metadata) -> bool {
+            metadata.level() <= Level::Debug
+        }
+
+        fn log(&self, record: &Record) {
+            self.0.lock().unwrap().push(format!("{} {} {}{}", record.level(), record.target(), record.args(),
+                                                AsText(record.key_values())));
+        }
+
+        fn flush(&self) {}
+    }
+
+    #[test]
+    fn test_fblog_layer() {
+        // the max level is global, the logger of other tests must not see it changing
+        let _lock = crate::test_helper::logger_lock();
+        let max_level = log::max_level();
+        log::set_max_level(log::LevelFilter::Trace);
+        let capture: &'static CaptureLog = Box::leak(Box::new(CaptureLog(Mutex::new(vec![]))));
+        let subscriber = tracing_subscriber::registry().with(FblogLayer::with_logger(capture));
+        tracing::subscriber::with_default(subscriber, || {
+            tracing::info!(target: "portal", "started");
+            let request = tracing::info_span!("request", request_id = "r-81", user_id = tracing::field::Empty);
+            let _request = request.enter();
+            request.record("user_id", 42);
+            let query = tracing::debug_span!("db_query", table = "users");
+            let _query = query.enter();
+            tracing::warn!(target: "portal", elapsed_ms = 1200, table = "rooms", "slow query");
+            tracing::trace!("filtered out");
+        });
+        log::set_max_level(max_level);
+        assert_eq!(*capture.0.lock().unwrap(), vec![
+            "INFO portal started".to_string(),
+            "WARN portal request: db_query: slow query request_id=r-81 user_id=42 elapsed_ms=1200 table=rooms".to_string(),
+        ]);
+    }
+}